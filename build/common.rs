@@ -3,6 +3,7 @@
 // because they rely on their own dependencies and so on
 
 pub type DesktopHandler = String;
+pub type LaunchTarget = String;
 pub type MimeOrExtension = String;
 pub type UserPath = String;
 
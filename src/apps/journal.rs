@@ -0,0 +1,521 @@
+use crate::{
+    apps::DesktopList,
+    common::render_table,
+    error::{Error, Result},
+    utils,
+};
+use itertools::Itertools;
+use mime::Mime;
+use serde::{Deserialize, Serialize};
+use serde_with::{serde_as, DisplayFromStr};
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    io::{Read, Write},
+    path::PathBuf,
+};
+use tabled::Tabled;
+
+/// Maximum number of changes to remember
+/// Oldest changes are forgotten first
+const MAX_ENTRIES: usize = 100;
+
+/// Section of mimeapps.list
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Section {
+    /// Added associations
+    Added,
+    /// Default applications
+    Default,
+}
+
+impl Display for Section {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Added => "added",
+            Self::Default => "default",
+        })
+    }
+}
+
+/// Change made to the handlers of a single mime
+#[serde_as]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MimeChange {
+    pub section: Section,
+    #[serde_as(as = "DisplayFromStr")]
+    pub mime: Mime,
+    /// Handlers before the change, if there were any
+    pub before: Option<DesktopList>,
+    /// Handlers after the change, if there are any
+    pub after: Option<DesktopList>,
+}
+
+/// Kind of change made to the handlers of a mime
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeKind {
+    /// Handlers were set where there were none
+    Added,
+    /// Handlers were removed
+    Removed,
+    /// Handlers were replaced or rearranged
+    Changed,
+}
+
+impl Display for ChangeKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Added => "added",
+            Self::Removed => "removed",
+            Self::Changed => "changed",
+        })
+    }
+}
+
+impl MimeChange {
+    /// Get the changes between two sets of associations of a given section
+    /// An empty list of handlers is the same as a missing one
+    pub fn between(
+        section: Section,
+        before: &BTreeMap<Mime, DesktopList>,
+        after: &BTreeMap<Mime, DesktopList>,
+    ) -> Vec<Self> {
+        let non_empty = |handlers: Option<&DesktopList>| {
+            handlers.filter(|h| !h.is_empty()).cloned()
+        };
+
+        before
+            .keys()
+            .chain(after.keys())
+            .sorted()
+            .dedup()
+            .filter_map(|mime| {
+                let old = non_empty(before.get(mime));
+                let new = non_empty(after.get(mime));
+                (old != new).then(|| Self {
+                    section,
+                    mime: mime.clone(),
+                    before: old,
+                    after: new,
+                })
+            })
+            .collect()
+    }
+
+    /// Get the kind of the change
+    pub fn kind(&self) -> ChangeKind {
+        match (&self.before, &self.after) {
+            (None, _) => ChangeKind::Added,
+            (_, None) => ChangeKind::Removed,
+            _ => ChangeKind::Changed,
+        }
+    }
+}
+
+impl Display for MimeChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let display = |handlers: &Option<DesktopList>| {
+            handlers
+                .as_ref()
+                .map_or("(none)".to_string(), |h| h.iter().join(", "))
+        };
+
+        write!(
+            f,
+            "{}: {} -> {}",
+            self.mime,
+            display(&self.before),
+            display(&self.after)
+        )
+    }
+}
+
+/// Single command's worth of changes to mimeapps.list
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JournalEntry {
+    /// Unix timestamp of when the changes were made
+    pub time: u64,
+    /// Arguments of the command that made the changes
+    pub command: String,
+    pub changes: Vec<MimeChange>,
+}
+
+/// Bounded record of changes made to mimeapps.list, to allow undoing and redoing them
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Journal {
+    /// Changes, from oldest to newest
+    entries: Vec<JournalEntry>,
+    /// Number of the newest entries that have been undone
+    undone: usize,
+    /// Where the journal was read from and is saved to, if it is backed by a file
+    #[serde(skip)]
+    path: Option<PathBuf>,
+}
+
+/// Internal helper struct for turning journal entries into tabular data
+#[derive(Tabled, Serialize)]
+struct HistoryRow {
+    /// Position in the history, 1 being the newest
+    index: usize,
+    /// Unix timestamp of when the changes were made
+    #[tabled(display_with("Self::display_time", self))]
+    time: u64,
+    command: String,
+    #[tabled(display_with("Self::display_changes", self))]
+    changes: Vec<String>,
+    undone: bool,
+}
+
+impl HistoryRow {
+    /// Display how long ago the changes were made
+    fn display_time(&self) -> String {
+        utils::time_ago(self.time)
+    }
+
+    /// Display each change on its own line
+    fn display_changes(&self) -> String {
+        self.changes.join("\n")
+    }
+}
+
+impl Journal {
+    /// Record changes made by the current command just now
+    pub fn record(&mut self, changes: Vec<MimeChange>) {
+        let command = std::env::args().skip(1).join(" ");
+        self.record_at(command, changes, utils::now())
+    }
+
+    /// Record changes made by a given command at a given time
+    /// Any undone changes are forgotten, as they can no longer be redone
+    fn record_at(
+        &mut self,
+        command: String,
+        changes: Vec<MimeChange>,
+        time: u64,
+    ) {
+        if changes.is_empty() {
+            return;
+        }
+
+        self.entries.truncate(self.entries.len() - self.undone);
+        self.undone = 0;
+
+        self.entries.push(JournalEntry {
+            time,
+            command,
+            changes,
+        });
+
+        if self.entries.len() > MAX_ENTRIES {
+            self.entries.drain(..self.entries.len() - MAX_ENTRIES);
+        }
+    }
+
+    /// Mark up to `n` of the newest changes as undone
+    /// Returns them from newest to oldest, which is the order they should be reverted in
+    pub fn undo(&mut self, n: usize) -> Result<Vec<JournalEntry>> {
+        let available = self.entries.len() - self.undone;
+        if available == 0 {
+            return Err(Error::NoHistory("undo"));
+        }
+
+        let n = n.min(available);
+        let undone = self.entries[available - n..available]
+            .iter()
+            .rev()
+            .cloned()
+            .collect_vec();
+        self.undone += n;
+
+        Ok(undone)
+    }
+
+    /// Mark up to `n` of the oldest undone changes as redone
+    /// Returns them from oldest to newest, which is the order they should be reapplied in
+    pub fn redo(&mut self, n: usize) -> Result<Vec<JournalEntry>> {
+        if self.undone == 0 {
+            return Err(Error::NoHistory("redo"));
+        }
+
+        let n = n.min(self.undone);
+        let start = self.entries.len() - self.undone;
+        let redone = self.entries[start..start + n].to_vec();
+        self.undone -= n;
+
+        Ok(redone)
+    }
+
+    /// Print the history of changes, newest first
+    pub fn print<W: Write>(
+        &self,
+        writer: &mut W,
+        output_json: bool,
+        terminal_output: bool,
+    ) -> Result<()> {
+        let rows = self
+            .entries
+            .iter()
+            .rev()
+            .enumerate()
+            .map(|(i, entry)| HistoryRow {
+                index: i + 1,
+                time: entry.time,
+                command: entry.command.clone(),
+                changes: entry.changes.iter().map(|c| c.to_string()).collect(),
+                undone: i < self.undone,
+            })
+            .collect_vec();
+
+        let table = if output_json {
+            serde_json::to_string(&rows)?
+        } else {
+            render_table(&rows, terminal_output)
+        };
+
+        writeln!(writer, "{table}")?;
+
+        Ok(())
+    }
+
+    /// Get the path to handlr's journal file
+    #[mutants::skip] // Cannot test directly, depends on system state
+    fn path() -> Result<PathBuf> {
+        Ok(xdg::BaseDirectories::with_prefix("handlr")?
+            .place_state_file("journal.json")?)
+    }
+
+    /// Read and parse the journal file
+    /// If it cannot be parsed, it is started over
+    #[mutants::skip] // Cannot test directly, depends on system state
+    pub fn read() -> Result<Self> {
+        let path = Self::path()?;
+
+        let mut journal = if path.exists() {
+            Self::read_from(std::fs::File::open(&path)?).unwrap_or_default()
+        } else {
+            Self::default()
+        };
+        journal.path = Some(path);
+
+        Ok(journal)
+    }
+
+    /// Read the journal file again, picking up entries recorded by other processes since
+    /// Journals that are not backed by a file are left as they are
+    #[mutants::skip] // Cannot test directly, depends on system state
+    pub fn reload(&mut self) -> Result<()> {
+        if self.path.is_some() {
+            *self = Self::read()?;
+        }
+        Ok(())
+    }
+
+    /// Deserialize Journal from reader
+    /// Makes testing easier
+    fn read_from<R: Read>(reader: R) -> Result<Self> {
+        let mut journal: Self = serde_json::from_reader(reader)?;
+        // A hand-edited journal could claim more undone entries than it has
+        journal.undone = journal.undone.min(journal.entries.len());
+        Ok(journal)
+    }
+
+    /// Save the journal file
+    #[mutants::skip] // Cannot test directly, alters system state
+    pub fn save(&self) -> Result<()> {
+        match &self.path {
+            Some(path) => utils::write_atomic(path, |file| self.save_to(file)),
+            None => Ok(()),
+        }
+    }
+
+    /// Serialize Journal and write to writer
+    /// Makes testing easier
+    fn save_to<W: Write>(&self, writer: &mut W) -> Result<()> {
+        serde_json::to_writer(writer, self)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use std::str::FromStr;
+
+    // Helper function to create a change to text/plain
+    fn change(before: Option<&str>, after: Option<&str>) -> Result<MimeChange> {
+        Ok(MimeChange {
+            section: Section::Default,
+            mime: mime::TEXT_PLAIN,
+            before: before.map(DesktopList::from_str).transpose()?,
+            after: after.map(DesktopList::from_str).transpose()?,
+        })
+    }
+
+    // Helper function to create a journal with a few arbitrary entries
+    fn journal() -> Result<Journal> {
+        let mut journal = Journal::default();
+
+        journal.record_at(
+            "set text/plain nvim.desktop".into(),
+            vec![change(None, Some("nvim.desktop"))?],
+            100,
+        );
+        journal.record_at(
+            "add text/plain Helix.desktop".into(),
+            vec![change(
+                Some("nvim.desktop"),
+                Some("nvim.desktop;Helix.desktop"),
+            )?],
+            200,
+        );
+        journal.record_at(
+            "unset text/plain".into(),
+            vec![change(Some("nvim.desktop;Helix.desktop"), None)?],
+            300,
+        );
+
+        Ok(journal)
+    }
+
+    #[test]
+    fn undo_and_redo() -> Result<()> {
+        let mut journal = journal()?;
+        let commands = |entries: Vec<JournalEntry>| {
+            entries.into_iter().map(|e| e.command).collect_vec()
+        };
+
+        assert_eq!(
+            commands(journal.undo(2)?),
+            ["unset text/plain", "add text/plain Helix.desktop"]
+        );
+        assert_eq!(commands(journal.undo(5)?), ["set text/plain nvim.desktop"]);
+        assert!(matches!(journal.undo(1), Err(Error::NoHistory(_))));
+
+        assert_eq!(
+            commands(journal.redo(2)?),
+            [
+                "set text/plain nvim.desktop",
+                "add text/plain Helix.desktop"
+            ]
+        );
+
+        // New changes make undone ones impossible to redo
+        journal.record_at(
+            "set text/plain kak.desktop".into(),
+            vec![change(
+                Some("nvim.desktop;Helix.desktop"),
+                Some("kak.desktop"),
+            )?],
+            400,
+        );
+        assert!(matches!(journal.redo(1), Err(Error::NoHistory(_))));
+        assert_eq!(journal.entries.len(), 3);
+
+        Ok(())
+    }
+
+    #[test]
+    fn print_history() -> Result<()> {
+        let mut journal = journal()?;
+        journal.undo(1)?;
+
+        let mut buffer = Vec::new();
+        journal.print(&mut buffer, true, false)?;
+        goldie::assert!(String::from_utf8(buffer)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn journal_round_trip() -> Result<()> {
+        let journal = journal()?;
+
+        let mut buffer = Vec::new();
+        journal.save_to(&mut buffer)?;
+
+        assert_eq!(Journal::read_from(buffer.as_slice())?, journal);
+
+        Ok(())
+    }
+
+    #[test]
+    fn clamp_undone_entries() -> Result<()> {
+        let mut journal = journal()?;
+        journal.undone = 5;
+
+        let mut buffer = Vec::new();
+        journal.save_to(&mut buffer)?;
+        let mut journal = Journal::read_from(buffer.as_slice())?;
+
+        assert_eq!(journal.undone, 3);
+        assert!(matches!(journal.undo(1), Err(Error::NoHistory(_))));
+        assert_eq!(journal.redo(5)?.len(), 3);
+
+        Ok(())
+    }
+
+    #[test]
+    fn changes_between() -> Result<()> {
+        let associations = |entries: &[(&str, &str)]| -> Result<_> {
+            entries
+                .iter()
+                .map(|(mime, handlers)| {
+                    Ok((
+                        Mime::from_str(mime)?,
+                        DesktopList::from_str(handlers)?,
+                    ))
+                })
+                .collect::<Result<BTreeMap<_, _>>>()
+        };
+
+        let before = associations(&[
+            ("text/plain", "nvim.desktop;Helix.desktop"),
+            ("text/html", "firefox.desktop"),
+            ("image/png", "gimp.desktop"),
+            ("video/mp4", ""),
+        ])?;
+        let after = associations(&[
+            ("text/plain", "Helix.desktop;nvim.desktop"),
+            ("image/png", "gimp.desktop"),
+            ("video/mp4", "mpv.desktop"),
+        ])?;
+
+        let changes = MimeChange::between(Section::Default, &before, &after);
+
+        assert_eq!(
+            changes
+                .iter()
+                .map(|c| format!("{}: {}", c.kind(), c))
+                .collect_vec(),
+            [
+                "removed: text/html: firefox.desktop -> (none)",
+                "changed: text/plain: nvim.desktop, Helix.desktop -> Helix.desktop, nvim.desktop",
+                "added: video/mp4: (none) -> mpv.desktop",
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn forget_oldest_changes() -> Result<()> {
+        let mut journal = Journal::default();
+
+        for i in 0..=MAX_ENTRIES as u64 {
+            journal.record_at(
+                i.to_string(),
+                vec![change(None, Some("a.desktop"))?],
+                i,
+            );
+        }
+
+        assert_eq!(journal.entries.len(), MAX_ENTRIES);
+        assert_eq!(journal.entries[0].command, "1");
+
+        Ok(())
+    }
+}
@@ -1,5 +1,9 @@
+mod journal;
 mod system;
+mod usage;
 mod user;
 
+pub use journal::{ChangeKind, Journal, MimeChange, Section};
 pub use system::SystemApps;
-pub use user::{DesktopList, MimeApps};
+pub use usage::UsageHistory;
+pub use user::{DesktopList, MimeApps, Reorder};
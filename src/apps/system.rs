@@ -1,10 +1,20 @@
 use crate::{
     apps::DesktopList,
-    common::{DesktopEntry, DesktopHandler, Handleable},
+    common::{
+        mime_ancestors, render_table, DesktopEntry, DesktopHandler, Handleable,
+    },
     error::Result,
 };
+use itertools::Itertools;
 use mime::Mime;
-use std::{collections::BTreeMap, convert::TryFrom, ffi::OsString};
+use serde::Serialize;
+use std::{
+    collections::BTreeMap,
+    convert::TryFrom,
+    ffi::{OsStr, OsString},
+    io::Write,
+};
+use tabled::Tabled;
 
 #[derive(Debug, Default, Clone)]
 pub struct SystemApps {
@@ -25,6 +35,36 @@ impl SystemApps {
         Some(self.get_handlers(mime)?.front()?.clone())
     }
 
+    /// Get the list of handlers associated with a given mime
+    /// followed by the handlers associated with its parent mimes
+    pub fn get_handlers_with_fallback(&self, mime: &Mime) -> DesktopList {
+        let mut handlers = DesktopList::default();
+
+        std::iter::once(mime.clone())
+            .chain(mime_ancestors(mime))
+            .filter_map(|m| self.associations.get(&m))
+            .flat_map(|list| list.iter())
+            .unique()
+            .for_each(|h| handlers.push_back(h.clone()));
+
+        handlers
+    }
+
+    /// Get every handler on the system, whether it is associated with a mime or not
+    pub fn get_all_handlers(&self) -> DesktopList {
+        let mut handlers = DesktopList::default();
+
+        self.associations
+            .values()
+            .flat_map(|list| list.iter())
+            .chain(self.unassociated.iter())
+            .unique()
+            .sorted()
+            .for_each(|h| handlers.push_back(h.clone()));
+
+        handlers
+    }
+
     /// Get all system-level desktop entries on the system
     #[mutants::skip] // Cannot test directly, depends on system state
     pub fn get_entries(
@@ -80,6 +120,45 @@ impl SystemApps {
             .find(|h| h.is_terminal_emulator())
     }
 
+    /// Print a table of the given desktop entries,
+    /// optionally only those that match a search term or support any of the given mimes
+    pub fn print_entries<W, I>(
+        writer: &mut W,
+        entries: I,
+        search: Option<&str>,
+        mimes: Option<&[Mime]>,
+        output_json: bool,
+        terminal_output: bool,
+    ) -> Result<()>
+    where
+        W: Write,
+        I: IntoIterator<Item = (OsString, DesktopEntry)>,
+    {
+        // If output is a terminal, optimize for readability
+        // Otherwise, if piped, optimize for parseability
+        let separator = if terminal_output { ",\n" } else { ", " };
+
+        let rows = entries
+            .into_iter()
+            .filter(|(_, entry)| search.is_none_or(|s| entry.matches_search(s)))
+            .filter(|(_, entry)| {
+                mimes.is_none_or(|m| m.iter().any(|m| entry.supports_mime(m)))
+            })
+            .map(|(id, entry)| AppsEntry::new(&id, entry, separator))
+            .sorted_by(|a, b| a.id.cmp(&b.id))
+            .collect_vec();
+
+        let table = if output_json {
+            serde_json::to_string(&rows)?
+        } else {
+            render_table(&rows, terminal_output)
+        };
+
+        writeln!(writer, "{table}")?;
+
+        Ok(())
+    }
+
     #[cfg(test)]
     /// Internal helper function for testing
     pub fn add_unassociated(&mut self, handler: DesktopHandler) {
@@ -87,9 +166,53 @@ impl SystemApps {
     }
 }
 
+/// Internal helper struct for turning desktop entries into tabular data
+#[derive(Tabled, Serialize)]
+struct AppsEntry {
+    id: String,
+    name: String,
+    exec: String,
+    terminal: bool,
+    #[tabled(display_with("Self::display_mimes", self))]
+    mimes: Vec<String>,
+    #[tabled(display_with("Self::display_categories", self))]
+    categories: Vec<String>,
+    #[tabled(skip)]
+    #[serde(skip_serializing)]
+    // This field should not appear in any output
+    // It is only used for determining how to render output
+    separator: String,
+}
+
+impl AppsEntry {
+    /// Create a new `AppsEntry`
+    fn new(id: &OsStr, entry: DesktopEntry, separator: &str) -> Self {
+        Self {
+            id: id.to_string_lossy().into_owned(),
+            name: entry.name,
+            exec: entry.exec,
+            terminal: entry.terminal,
+            mimes: entry.mime_type.iter().map(|m| m.to_string()).collect_vec(),
+            categories: entry.categories,
+            separator: separator.to_string(),
+        }
+    }
+
+    /// Display list of mimes as a string
+    fn display_mimes(&self) -> String {
+        self.mimes.join(&self.separator)
+    }
+
+    /// Display list of categories as a string
+    fn display_categories(&self) -> String {
+        self.categories.join(&self.separator)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::str::FromStr;
 
     #[test]
     fn get_handlers() -> Result<()> {
@@ -124,4 +247,150 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn handlers_with_fallback() -> Result<()> {
+        let mut associations: BTreeMap<Mime, DesktopList> = BTreeMap::new();
+        associations
+            .entry(Mime::from_str("text/markdown")?)
+            .or_default()
+            .push_back(DesktopHandler::assume_valid(
+                "apostrophe.desktop".into(),
+            ));
+        associations
+            .entry(mime::TEXT_PLAIN)
+            .or_default()
+            .push_back(DesktopHandler::assume_valid("helix.desktop".into()));
+
+        let mut system_apps = SystemApps {
+            associations,
+            ..Default::default()
+        };
+        system_apps.add_unassociated(DesktopHandler::assume_valid(
+            "wezterm.desktop".into(),
+        ));
+
+        assert_eq!(
+            system_apps
+                .get_handlers_with_fallback(&Mime::from_str("text/markdown")?)
+                .iter()
+                .map(|h| h.to_string())
+                .collect::<Vec<_>>(),
+            ["apostrophe.desktop", "helix.desktop"]
+        );
+        assert!(system_apps
+            .get_handlers_with_fallback(&mime::IMAGE_PNG)
+            .is_empty());
+        assert_eq!(
+            system_apps
+                .get_all_handlers()
+                .iter()
+                .map(|h| h.to_string())
+                .collect::<Vec<_>>(),
+            ["apostrophe.desktop", "helix.desktop", "wezterm.desktop"]
+        );
+
+        Ok(())
+    }
+
+    // Helper function to get desktop entries from the test fixtures
+    fn entries() -> Result<Vec<(OsString, DesktopEntry)>> {
+        [
+            "tests/cmus.desktop",
+            "tests/Helix.desktop",
+            "tests/org.wezfurlong.wezterm.desktop",
+        ]
+        .into_iter()
+        .map(|path| {
+            let path = std::path::PathBuf::from(path);
+            Ok((
+                path.file_name().expect("Fixture has a file name").into(),
+                DesktopEntry::try_from(path)?,
+            ))
+        })
+        .collect()
+    }
+
+    #[test]
+    fn print_apps_terminal() -> Result<()> {
+        let mut buffer = Vec::new();
+        SystemApps::print_entries(
+            &mut buffer,
+            entries()?,
+            None,
+            None,
+            false,
+            true,
+        )?;
+        goldie::assert!(String::from_utf8(buffer)?);
+        Ok(())
+    }
+
+    #[test]
+    fn print_apps_piped() -> Result<()> {
+        let mut buffer = Vec::new();
+        SystemApps::print_entries(
+            &mut buffer,
+            entries()?,
+            None,
+            None,
+            false,
+            false,
+        )?;
+        goldie::assert!(String::from_utf8(buffer)?);
+        Ok(())
+    }
+
+    #[test]
+    fn print_apps_json() -> Result<()> {
+        let mut buffer = Vec::new();
+        SystemApps::print_entries(
+            &mut buffer,
+            entries()?,
+            None,
+            None,
+            true,
+            false,
+        )?;
+        goldie::assert!(String::from_utf8(buffer)?);
+        Ok(())
+    }
+
+    #[test]
+    fn filter_apps() -> Result<()> {
+        let ids =
+            |search: Option<&str>, mimes: Option<&[Mime]>| -> Result<String> {
+                let mut buffer = Vec::new();
+                SystemApps::print_entries(
+                    &mut buffer,
+                    entries()?,
+                    search,
+                    mimes,
+                    false,
+                    false,
+                )?;
+                Ok(String::from_utf8(buffer)?
+                    .lines()
+                    .skip(1)
+                    .filter_map(|line| line.split('\t').next())
+                    .join(" "))
+            };
+
+        assert_eq!(
+            ids(Some("terminal"), None)?,
+            "org.wezfurlong.wezterm.desktop"
+        );
+        assert_eq!(ids(Some("EDITOR"), None)?, "Helix.desktop");
+        assert_eq!(
+            ids(None, Some(&[Mime::from_str("audio/ogg")?]))?,
+            "cmus.desktop"
+        );
+        assert_eq!(
+            ids(None, Some(&[Mime::from_str("text/x-markdown")?]))?,
+            "Helix.desktop"
+        );
+        assert_eq!(ids(Some("music"), Some(&[mime::TEXT_PLAIN]))?, "");
+
+        Ok(())
+    }
 }
@@ -0,0 +1,336 @@
+use crate::{
+    common::{render_table, DesktopHandler},
+    config::HandlerOrder,
+    error::Result,
+    utils,
+};
+use mime::Mime;
+use serde::{Deserialize, Serialize};
+use serde_with::{serde_as, DisplayFromStr};
+use std::{
+    io::{Read, Write},
+    path::PathBuf,
+};
+use tabled::Tabled;
+
+/// Maximum number of mime/handler pairs to remember
+/// Least recently used pairs are forgotten first
+const MAX_ENTRIES: usize = 512;
+
+/// Record of which handlers have been used to open which mimes
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UsageHistory {
+    entries: Vec<UsageEntry>,
+    /// Where the history was read from and is saved to, if it is backed by a file
+    #[serde(skip)]
+    path: Option<PathBuf>,
+}
+
+/// How often and how recently a handler has been used for a mime
+#[serde_as]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Tabled)]
+pub struct UsageEntry {
+    #[serde_as(as = "DisplayFromStr")]
+    mime: Mime,
+    #[serde_as(as = "DisplayFromStr")]
+    handler: DesktopHandler,
+    count: u64,
+    /// Unix timestamp of the last time the handler was used
+    #[tabled(display_with("Self::display_last_used", self))]
+    last_used: u64,
+}
+
+impl UsageEntry {
+    /// Display how long ago the handler was last used
+    fn display_last_used(&self) -> String {
+        utils::time_ago(self.last_used)
+    }
+}
+
+impl UsageHistory {
+    /// Record that a handler was used to open a given mime just now
+    pub fn record(&mut self, mime: &Mime, handler: &DesktopHandler) {
+        self.record_at(mime, handler, utils::now())
+    }
+
+    /// Record that a handler was used to open a given mime at a given time
+    fn record_at(&mut self, mime: &Mime, handler: &DesktopHandler, time: u64) {
+        match self
+            .entries
+            .iter_mut()
+            .find(|e| e.mime == *mime && e.handler == *handler)
+        {
+            Some(entry) => {
+                entry.count += 1;
+                entry.last_used = entry.last_used.max(time);
+            }
+            None => self.entries.push(UsageEntry {
+                mime: mime.clone(),
+                handler: handler.clone(),
+                count: 1,
+                last_used: time,
+            }),
+        }
+
+        // Forget the least recently used entries if there are too many
+        if self.entries.len() > MAX_ENTRIES {
+            self.entries.sort_by_key(|e| std::cmp::Reverse(e.last_used));
+            self.entries.truncate(MAX_ENTRIES);
+        }
+    }
+
+    /// Get the usage entry of a given handler for a given mime, if any
+    fn get(
+        &self,
+        mime: &Mime,
+        handler: &DesktopHandler,
+    ) -> Option<&UsageEntry> {
+        self.entries
+            .iter()
+            .find(|e| e.mime == *mime && e.handler == *handler)
+    }
+
+    /// Sort a list of items by how their handlers have been used for a given mime
+    /// Items with no recorded usage keep their relative order after the used ones
+    pub fn sort_by_usage<T, F>(
+        &self,
+        mime: &Mime,
+        items: &mut [T],
+        order: HandlerOrder,
+        handler: F,
+    ) where
+        F: Fn(&T) -> &DesktopHandler,
+    {
+        let key = |item: &T| {
+            self.get(mime, handler(item))
+                .map_or((0, 0), |e| match order {
+                    HandlerOrder::Recent => (e.last_used, e.count),
+                    HandlerOrder::Frequent => (e.count, e.last_used),
+                    HandlerOrder::Config => (0, 0),
+                })
+        };
+
+        // Stable sort, so ties keep the configured order
+        items.sort_by_key(|item| std::cmp::Reverse(key(item)));
+    }
+
+    /// Get usage entries, optionally only for the given mimes,
+    /// sorted by mime and then by most used
+    fn stats(&self, mimes: Option<&[Mime]>) -> Vec<UsageEntry> {
+        let mut entries = self
+            .entries
+            .iter()
+            .filter(|e| mimes.is_none_or(|m| m.contains(&e.mime)))
+            .cloned()
+            .collect::<Vec<_>>();
+
+        entries.sort_by(|a, b| {
+            a.mime
+                .as_ref()
+                .cmp(b.mime.as_ref())
+                .then(b.count.cmp(&a.count))
+                .then(b.last_used.cmp(&a.last_used))
+        });
+
+        entries
+    }
+
+    /// Print usage statistics, optionally only for the given mimes
+    pub fn print<W: Write>(
+        &self,
+        writer: &mut W,
+        mimes: Option<&[Mime]>,
+        output_json: bool,
+        terminal_output: bool,
+    ) -> Result<()> {
+        let rows = self.stats(mimes);
+
+        let table = if output_json {
+            serde_json::to_string(&rows)?
+        } else {
+            render_table(&rows, terminal_output)
+        };
+
+        writeln!(writer, "{table}")?;
+
+        Ok(())
+    }
+
+    /// Get the path to handlr's usage history file
+    #[mutants::skip] // Cannot test directly, depends on system state
+    fn path() -> Result<PathBuf> {
+        Ok(xdg::BaseDirectories::with_prefix("handlr")?
+            .place_state_file("usage.json")?)
+    }
+
+    /// Read and parse the usage history file
+    /// If it cannot be parsed, it is started over
+    #[mutants::skip] // Cannot test directly, depends on system state
+    pub fn read() -> Result<Self> {
+        let path = Self::path()?;
+
+        let mut usage = if path.exists() {
+            Self::read_from(std::fs::File::open(&path)?).unwrap_or_default()
+        } else {
+            Self::default()
+        };
+        usage.path = Some(path);
+
+        Ok(usage)
+    }
+
+    /// Deserialize UsageHistory from reader
+    /// Makes testing easier
+    fn read_from<R: Read>(reader: R) -> Result<Self> {
+        Ok(serde_json::from_reader(reader)?)
+    }
+
+    /// Save the usage history file
+    #[mutants::skip] // Cannot test directly, alters system state
+    pub fn save(&self) -> Result<()> {
+        match &self.path {
+            Some(path) => utils::write_atomic(path, |file| self.save_to(file)),
+            None => Ok(()),
+        }
+    }
+
+    /// Serialize UsageHistory and write to writer
+    /// Makes testing easier
+    fn save_to<W: Write>(&self, writer: &mut W) -> Result<()> {
+        serde_json::to_writer(writer, self)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use std::str::FromStr;
+
+    // Helper function to create a history with a few arbitrary entries
+    fn history() -> Result<UsageHistory> {
+        let mut history = UsageHistory::default();
+        let helix = DesktopHandler::from_str("Helix.desktop")?;
+        let nvim = DesktopHandler::from_str("nvim.desktop")?;
+        let mpv = DesktopHandler::from_str("mpv.desktop")?;
+
+        history.record_at(&mime::TEXT_PLAIN, &helix, 100);
+        history.record_at(&mime::TEXT_PLAIN, &helix, 200);
+        history.record_at(&mime::TEXT_PLAIN, &nvim, 300);
+        history.record_at(&Mime::from_str("video/mp4")?, &mpv, 50);
+
+        Ok(history)
+    }
+
+    #[test]
+    fn record_usage() -> Result<()> {
+        let history = history()?;
+        let helix = DesktopHandler::from_str("Helix.desktop")?;
+
+        let entry = history
+            .get(&mime::TEXT_PLAIN, &helix)
+            .expect("Could not get usage entry");
+        assert_eq!(entry.count, 2);
+        assert_eq!(entry.last_used, 200);
+        assert!(history.get(&mime::TEXT_HTML, &helix).is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn sort_handlers_by_usage() -> Result<()> {
+        let history = history()?;
+        let handlers = ["kakoune.desktop", "Helix.desktop", "nvim.desktop"]
+            .iter()
+            .map(|h| DesktopHandler::from_str(h))
+            .collect::<Result<Vec<_>>>()?;
+
+        let sorted = |order| {
+            let mut handlers = handlers.clone();
+            history.sort_by_usage(
+                &mime::TEXT_PLAIN,
+                &mut handlers,
+                order,
+                |h| h,
+            );
+            handlers.iter().map(|h| h.to_string()).collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            sorted(HandlerOrder::Config),
+            ["kakoune.desktop", "Helix.desktop", "nvim.desktop"]
+        );
+        assert_eq!(
+            sorted(HandlerOrder::Recent),
+            ["nvim.desktop", "Helix.desktop", "kakoune.desktop"]
+        );
+        assert_eq!(
+            sorted(HandlerOrder::Frequent),
+            ["Helix.desktop", "nvim.desktop", "kakoune.desktop"]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn usage_stats() -> Result<()> {
+        let history = history()?;
+
+        let stats = history.stats(None);
+        assert_eq!(
+            stats
+                .iter()
+                .map(|e| (e.mime.to_string(), e.handler.to_string()))
+                .collect::<Vec<_>>(),
+            [
+                ("text/plain", "Helix.desktop"),
+                ("text/plain", "nvim.desktop"),
+                ("video/mp4", "mpv.desktop"),
+            ]
+            .map(|(m, h)| (m.to_string(), h.to_string()))
+        );
+
+        assert_eq!(history.stats(Some(&[mime::TEXT_PLAIN])).len(), 2);
+
+        let mut buffer = Vec::new();
+        history.print(&mut buffer, None, true, false)?;
+        goldie::assert!(String::from_utf8(buffer)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn usage_round_trip() -> Result<()> {
+        let history = history()?;
+
+        let mut buffer = Vec::new();
+        history.save_to(&mut buffer)?;
+
+        assert_eq!(UsageHistory::read_from(buffer.as_slice())?, history);
+
+        Ok(())
+    }
+
+    #[test]
+    fn forget_least_recently_used() -> Result<()> {
+        let mut history = UsageHistory::default();
+        let handler = DesktopHandler::from_str("Helix.desktop")?;
+
+        for i in 0..=MAX_ENTRIES as u64 {
+            history.record_at(
+                &Mime::from_str(&format!("text/x-test{i}"))?,
+                &handler,
+                i,
+            );
+        }
+
+        assert_eq!(history.entries.len(), MAX_ENTRIES);
+        assert!(history
+            .get(&Mime::from_str("text/x-test0")?, &handler)
+            .is_none());
+
+        Ok(())
+    }
+}
@@ -1,7 +1,12 @@
 use crate::{
-    common::{mime_types, DesktopHandler, Handleable},
+    apps::{MimeChange, Section, UsageHistory},
+    common::{
+        mime_types, unalias_mime, DesktopHandler, Diagnostic, Entry,
+        Handleable, IniDocument,
+    },
     config::ConfigFile,
     error::{Error, Result},
+    utils::{self, select},
 };
 use derive_more::{Deref, DerefMut};
 use itertools::Itertools;
@@ -13,8 +18,9 @@ use serde_with::{
 use std::{
     collections::{BTreeMap, VecDeque},
     fmt::Display,
+    fs::File,
     io::{Read, Write},
-    path::PathBuf,
+    path::{Path, PathBuf},
     str::FromStr,
 };
 use wildmatch::WildMatch;
@@ -33,8 +39,19 @@ pub struct MimeApps {
     #[serde(skip_serializing_if = "BTreeMap::is_empty")]
     #[serde_as(as = "BTreeMap<DisplayFromStr, _>")]
     pub default_apps: BTreeMap<Mime, DesktopList>,
+    /// The file as it was read, so that anything handlr does not touch is kept as is
+    #[serde(skip)]
+    document: IniDocument,
+    /// Where the file was read from and is saved to, if it is backed by one
+    #[serde(skip)]
+    path: Option<PathBuf>,
 }
 
+/// Section of mimeapps.list for added associations
+const ADDED_ASSOCIATIONS: &str = "Added Associations";
+/// Section of mimeapps.list for default applications
+const DEFAULT_APPLICATIONS: &str = "Default Applications";
+
 /// Helper struct for a list of `DesktopHandler`s
 #[serde_as]
 #[derive(
@@ -63,6 +80,63 @@ impl FromStr for DesktopList {
     }
 }
 
+impl DesktopList {
+    /// Rearrange the handlers in the list
+    /// Returns the handlers that were asked for but are not in the list, if any,
+    /// in which case the list is left untouched
+    fn rearrange(&mut self, reorder: &Reorder) -> Vec<DesktopHandler> {
+        let missing = reorder
+            .handlers()
+            .iter()
+            .filter(|h| !self.contains(h))
+            .cloned()
+            .collect_vec();
+
+        if missing.is_empty() {
+            let (mut moved, rest): (VecDeque<_>, VecDeque<_>) = self
+                .iter()
+                .cloned()
+                .partition(|h| reorder.handlers().contains(h));
+
+            // Keep the order the handlers were given in rather than their current order
+            moved.make_contiguous().sort_by_key(|h| {
+                reorder.handlers().iter().position(|r| r == h)
+            });
+
+            self.0 = match reorder {
+                Reorder::Demote(_) => rest.into_iter().chain(moved).collect(),
+                _ => moved.into_iter().chain(rest).collect(),
+            };
+        }
+
+        missing
+    }
+}
+
+/// How to rearrange a mime's list of handlers
+#[derive(Debug, Clone, PartialEq)]
+pub enum Reorder {
+    /// Move a handler to the front of the list, making it the default
+    Promote(DesktopHandler),
+    /// Move a handler to the back of the list
+    Demote(DesktopHandler),
+    /// Move the given handlers to the front of the list, in the given order
+    /// Handlers that are not given keep their relative order after them
+    Order(Vec<DesktopHandler>),
+}
+
+impl Reorder {
+    /// Get the handlers being moved
+    fn handlers(&self) -> &[DesktopHandler] {
+        match self {
+            Self::Promote(handler) | Self::Demote(handler) => {
+                std::slice::from_ref(handler)
+            }
+            Self::Order(handlers) => handlers,
+        }
+    }
+}
+
 impl Display for DesktopList {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{};", self.iter().join(";"))
@@ -127,60 +201,198 @@ impl MimeApps {
     }
 
     /// Entirely remove a given mime's default application association
-    pub fn unset_handler(&mut self, mime: &Mime) -> Option<()> {
+    /// Returns whether anything was removed
+    pub fn unset_handler(&mut self, mime: &Mime) -> bool {
         // If exact match is found, remove it
-        self.default_apps.remove(mime).map_or_else(
-            || {
-                let wildcard = WildMatch::new(mime.as_ref());
-                // Otherwise, remove all wildcard matches
-                self.default_apps
-                    .retain(|m, _| !wildcard.matches(m.as_ref()));
-                Some(())
-            },
-            |_| Some(()),
-        )
+        if self.default_apps.remove(mime).is_some() {
+            return true;
+        }
+
+        // Otherwise, remove all wildcard matches
+        let wildcard = WildMatch::new(mime.as_ref());
+        let before = self.default_apps.len();
+        self.default_apps
+            .retain(|m, _| !wildcard.matches(m.as_ref()));
+        self.default_apps.len() != before
+    }
+
+    /// Remove every default app and added association whose mime matches a given mime or glob,
+    /// or every one of them if none is given
+    /// A mime without wildcards matches its aliases too
+    pub fn reset(&mut self, pattern: Option<&str>) {
+        let matches = |mime: &Mime| match pattern {
+            None => true,
+            Some(pattern) if pattern.contains(['*', '?']) => {
+                WildMatch::new(pattern).matches(mime.as_ref())
+            }
+            Some(pattern) => Mime::from_str(pattern).is_ok_and(|pattern| {
+                unalias_mime(mime) == unalias_mime(&pattern)
+            }),
+        };
+
+        self.default_apps.retain(|mime, _| !matches(mime));
+        self.added_associations.retain(|mime, _| !matches(mime));
     }
 
     /// Remove a given handler from a given mime's default file associaion
+    /// Returns whether the handler was removed from anything
     pub fn remove_handler(
         &mut self,
         mime: &Mime,
         handler: &DesktopHandler,
-    ) -> Option<()> {
-        let handler_list = self.default_apps.entry(mime.clone()).or_default();
+    ) -> bool {
+        let remove = |handlers: &mut DesktopList| {
+            handlers
+                .iter()
+                .position(|x| *x == *handler)
+                .and_then(|pos| handlers.remove(pos))
+                .is_some()
+        };
 
         // If exact match is found, remove handler from it
-        handler_list
-            .iter()
-            .position(|x| *x == *handler)
-            .and_then(|pos| handler_list.remove(pos))
-            // Otherwise, look for a wildcard match
-            .map_or_else(
-                || {
-                    let wildcard = WildMatch::new(mime.as_ref());
-                    self.default_apps
-                        .clone()
-                        .keys()
-                        .filter(|m| wildcard.matches(m.as_ref()))
-                        .for_each(|m| {
-                            let handler_list =
-                                self.default_apps.entry(m.clone()).or_default();
-                            handler_list
-                                .iter()
-                                .position(|x| *x == *handler)
-                                .and_then(|pos| handler_list.remove(pos));
-                        });
-                    Some(())
-                },
-                |_| Some(()),
-            )
+        if self.default_apps.get_mut(mime).is_some_and(remove) {
+            return true;
+        }
+
+        // Otherwise, look for a wildcard match
+        let wildcard = WildMatch::new(mime.as_ref());
+        self.default_apps
+            .iter_mut()
+            .filter(|(m, _)| wildcard.matches(m.as_ref()))
+            .fold(false, |removed, (_, handlers)| remove(handlers) || removed)
+    }
+
+    /// Rearrange the default handlers of a given mime
+    /// If the mime has no list of its own, the lists of its aliases are used,
+    /// then those of mimes matching it as a wildcard, then that of the wildcard matching it
+    /// Returns the mimes whose lists were rearranged
+    pub fn reorder_handlers(
+        &mut self,
+        mime: &Mime,
+        reorder: &Reorder,
+    ) -> Result<Vec<Mime>> {
+        let has_handlers =
+            |m: &Mime| self.default_apps.get(m).is_some_and(|l| !l.is_empty());
+
+        let keys = if has_handlers(mime) {
+            vec![mime.clone()]
+        } else if let Some(alias) =
+            self.aliases_of(mime).into_iter().find(has_handlers)
+        {
+            vec![alias]
+        } else {
+            let wildcard = WildMatch::new(mime.as_ref());
+            let matches = self
+                .default_apps
+                .keys()
+                .filter(|m| wildcard.matches(m.as_ref()))
+                .cloned()
+                .collect_vec();
+
+            if matches.is_empty() {
+                self.get_wildcard_match(mime)
+                    .map(|(m, _)| m.clone())
+                    .into_iter()
+                    .collect_vec()
+            } else {
+                matches
+            }
+        };
+
+        let mut missing = Vec::new();
+        let mut reordered = Vec::new();
+
+        for key in keys {
+            let list = self.default_apps.entry(key.clone()).or_default();
+            match list.rearrange(reorder).as_slice() {
+                [] => reordered.push(key),
+                m if missing.is_empty() => missing = m.to_vec(),
+                _ => {}
+            }
+        }
+
+        match (reordered.is_empty(), missing.is_empty()) {
+            (true, true) => Err(Error::NotFound(mime.to_string())),
+            (true, false) => Err(Error::NotAssociated(
+                missing.iter().map(|h| h.to_string()).collect_vec(),
+                mime.clone(),
+            )),
+            _ => Ok(reordered),
+        }
+    }
+
+    /// Get the keys in mimeapps.list that are aliases of a given mime
+    pub fn aliases_of(&self, mime: &Mime) -> Vec<Mime> {
+        self.default_apps
+            .keys()
+            .chain(self.added_associations.keys())
+            .filter(|m| *m != mime && unalias_mime(m) == *mime)
+            .unique()
+            .cloned()
+            .collect_vec()
+    }
+
+    /// Get the list of handlers associated with a given mime from a given section,
+    /// or with one of its aliases if there is none
+    pub fn get_with_aliases<'a>(
+        &self,
+        section: &'a BTreeMap<Mime, DesktopList>,
+        mime: &Mime,
+    ) -> Option<&'a DesktopList> {
+        section
+            .get(mime)
+            .filter(|handlers| !handlers.is_empty())
+            .or_else(|| {
+                self.aliases_of(mime)
+                    .iter()
+                    .find_map(|alias| section.get(alias))
+            })
+    }
+
+    /// Rewrite keys that are aliases to their canonical mimes
+    /// If both are present, the canonical mime's handlers come first
+    /// Returns the renamed keys along with their canonical mimes
+    pub fn normalize_aliases(&mut self) -> Vec<(Mime, Mime)> {
+        let mut renamed = Vec::new();
+
+        for section in [&mut self.default_apps, &mut self.added_associations] {
+            let aliases = section
+                .keys()
+                .map(|m| (m.clone(), unalias_mime(m)))
+                .filter(|(m, canonical)| m != canonical)
+                .collect_vec();
+
+            for (alias, canonical) in aliases {
+                let handlers = section.remove(&alias).unwrap_or_default();
+                let list = section.entry(canonical.clone()).or_default();
+                handlers.iter().for_each(|h| {
+                    if !list.contains(h) {
+                        list.push_back(h.clone())
+                    }
+                });
+                renamed.push((alias, canonical));
+            }
+        }
+
+        renamed.sort();
+        renamed.dedup();
+        renamed
     }
 
     /// Get a list of handlers associated with a wildcard mime
     fn get_from_wildcard(&self, mime: &Mime) -> Option<&DesktopList> {
+        self.get_wildcard_match(mime).map(|(_, handlers)| handlers)
+    }
+
+    /// Get the wildcard mime that best matches a given mime, along with its list of handlers
+    pub fn get_wildcard_match(
+        &self,
+        mime: &Mime,
+    ) -> Option<(&Mime, &DesktopList)> {
         // Get the handlers that wildcard match the given mime
         let associations = self.default_apps.iter().filter(|(m, _)| {
-            wildmatch::WildMatch::new(m.as_ref()).matches(mime.as_ref())
+            m.as_ref().contains(['*', '?'])
+                && wildmatch::WildMatch::new(m.as_ref()).matches(mime.as_ref())
         });
 
         // Get the length of the longest wildcard that matches
@@ -196,24 +408,23 @@ impl MimeApps {
         // Loosely inspired by how globs are handled in xdg spec
         associations
             .filter(|(ref m, _)| m.as_ref().len() == biggest_wildcard_len)
-            .map(|(_, handlers)| handlers)
             .collect_vec()
             .first()
             .cloned()
     }
 
     /// Get the handler associated with a given mime from mimeapps.list's default apps
-    #[mutants::skip] // Cannot entirely test, namely cannot test selector or filtering
+    #[mutants::skip] // Cannot entirely test, namely cannot test selector
     pub fn get_handler_from_user(
         &self,
         mime: &Mime,
         config_file: &ConfigFile,
+        usage: &UsageHistory,
     ) -> Result<DesktopHandler> {
         let error = Error::NotFound(mime.to_string());
         // Check for an exact match first and then fall back to wildcard
         match self
-            .default_apps
-            .get(mime)
+            .get_with_aliases(&self.default_apps, mime)
             .or_else(|| self.get_from_wildcard(mime))
         {
             Some(handlers) => {
@@ -221,16 +432,19 @@ impl MimeApps {
                 let handlers = handlers
                     .iter()
                     .flat_map(|h| -> Result<(&DesktopHandler, String)> {
-                        // Filtering breaks testing, so treat every app as valid
-                        if cfg!(test) {
-                            Ok((h, h.to_string()))
-                        } else {
-                            Ok((h, h.get_entry()?.name))
-                        }
+                        Ok((h, h.get_entry()?.name))
                     })
                     .collect_vec();
 
                 if config_file.enable_selector && handlers.len() > 1 {
+                    let mut handlers = handlers;
+                    usage.sort_by_usage(
+                        mime,
+                        &mut handlers,
+                        config_file.selector_order,
+                        |h| h.0,
+                    );
+
                     let handler = {
                         let name = select(
                             &config_file.selector,
@@ -254,55 +468,181 @@ impl MimeApps {
         }
     }
 
-    /// Get the path to the user's mimeapps.list file
+    /// Get the path to the user's mimeapps.list file, creating its directory if needed
     #[mutants::skip] // Cannot test directly, depends on system state
-    fn path() -> Result<PathBuf> {
-        let mut config = xdg::BaseDirectories::new()?.get_config_home();
-        config.push("mimeapps.list");
-        Ok(config)
+    fn default_path() -> Result<PathBuf> {
+        Ok(xdg::BaseDirectories::new()?.place_config_file("mimeapps.list")?)
     }
 
-    /// Read and parse mimeapps.list
+    /// Read and parse the user's mimeapps.list, or a given file instead
+    /// The user's mimeapps.list is created if it does not exist,
+    /// but a given file is only created if `create` is true, e.g. for commands that modify it
     #[mutants::skip] // Cannot test directly, depends on system state
-    pub fn read() -> Result<Self> {
-        let exists = std::path::Path::new(&Self::path()?).exists();
+    pub fn read(path: Option<PathBuf>, create: bool) -> Result<Self> {
+        let (path, create) = match path {
+            Some(path) => (path, create),
+            None => (Self::default_path()?, true),
+        };
+        let create = create && !path.exists();
 
         let file = std::fs::OpenOptions::new()
-            .write(!exists)
-            .create(!exists)
+            .write(create)
+            .create(create)
             .read(true)
-            .open(Self::path()?)?;
+            .open(&path)
+            .map_err(|e| Error::OpenFile(path.clone(), e))?;
+
+        let mut mime_apps = Self::read_reporting(file, &path)?;
+        mime_apps.path = Some(path);
+
+        Ok(mime_apps)
+    }
+
+    /// Lock mimeapps.list and read it again, picking up changes made by other processes since
+    /// Associations that are not backed by a file are left as they are
+    #[mutants::skip] // Cannot test directly, alters system state
+    pub fn lock_and_reload(&mut self) -> Result<Option<File>> {
+        let Some(path) = self.path.clone() else {
+            return Ok(None);
+        };
+
+        let lock = Self::lock(Some(&path))?;
+        *self = Self::read(Some(path), true)?;
 
-        Self::read_from(file)
+        Ok(Some(lock))
     }
 
-    /// Deserialize MimeApps from reader
+    /// Read and parse a given mimeapps.list file, such as a reference copy in a dotfiles repo
+    #[mutants::skip] // Cannot test directly, depends on system state
+    pub fn read_file(path: &Path) -> Result<Self> {
+        Self::read_reporting(std::fs::File::open(path)?, path)
+    }
+
+    /// Deserialize MimeApps from reader, warning about any problems found
+    #[mutants::skip] // Cannot test directly, prints warnings
+    fn read_reporting<R: Read>(reader: R, path: &Path) -> Result<Self> {
+        let (mime_apps, diagnostics) = Self::read_from(reader)?;
+
+        for diagnostic in diagnostics {
+            utils::warn(&format!("{}:{diagnostic}", path.display()))
+        }
+
+        Ok(mime_apps)
+    }
+
+    /// Deserialize MimeApps from reader, along with any problems found
+    /// Problematic lines are skipped rather than making the whole file fail to parse
     /// Makes testing easier
-    fn read_from<R: Read>(reader: R) -> Result<Self> {
-        let mut mime_apps: MimeApps = serde_ini::de::from_read(reader)?;
+    fn read_from<R: Read>(mut reader: R) -> Result<(Self, Vec<Diagnostic>)> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+
+        let (document, mut diagnostics) = IniDocument::parse_bytes(&bytes);
+
+        // If a key appears several times, the last one takes precedence
+        let mut section = |name| {
+            document
+                .entries(name)
+                .filter_map(|entry| match Mime::from_str(entry.key) {
+                    Ok(mime) => {
+                        check_handlers(&entry, &mut diagnostics);
+                        Some((
+                            mime,
+                            DesktopList::from_str(entry.value)
+                                .unwrap_or_default(),
+                        ))
+                    }
+                    Err(e) => {
+                        diagnostics.push(Diagnostic {
+                            line: entry.line,
+                            column: entry.key_column,
+                            message: format!(
+                                "invalid mime '{}' ({e}), skipping line",
+                                entry.key
+                            ),
+                        });
+                        None
+                    }
+                })
+                .collect::<BTreeMap<_, _>>()
+        };
+
+        let mut mime_apps = MimeApps {
+            added_associations: section(ADDED_ASSOCIATIONS),
+            default_apps: section(DEFAULT_APPLICATIONS),
+            document: document.clone(),
+            ..Default::default()
+        };
 
         // Remove empty entries
         mime_apps
             .default_apps
             .retain(|_, handlers| !handlers.is_empty());
 
-        Ok(mime_apps)
+        diagnostics.sort_by_key(|d| (d.line, d.column));
+
+        Ok((mime_apps, diagnostics))
     }
 
     /// Save associations to mimeapps.list
+    /// Associations that are not backed by a file still have their document updated,
+    /// so that changes are tracked correctly
     #[mutants::skip] // Cannot test directly, alters system state
     pub fn save(&mut self) -> Result<()> {
-        if cfg!(test) {
-            Ok(())
-        } else {
-            let mut file = std::fs::OpenOptions::new()
-                .read(true)
-                .create(true)
-                .write(true)
-                .truncate(true)
-                .open(Self::path()?)?;
-
-            self.save_to(&mut file)
+        match self.path.clone() {
+            Some(path) => utils::write_atomic(&path, |file| self.save_to(file)),
+            None => self.save_to(&mut std::io::sink()),
+        }
+    }
+
+    /// Take an exclusive advisory lock on the user's mimeapps.list, or a given file instead,
+    /// waiting for any other holder
+    /// The lock is held on a `.lock` file next to it, and lasts until the returned file is dropped
+    #[mutants::skip] // Cannot test directly, alters system state
+    pub fn lock(path: Option<&Path>) -> Result<File> {
+        let mut lock = match path {
+            Some(path) => path.as_os_str().to_owned(),
+            None => Self::default_path()?.into_os_string(),
+        };
+        lock.push(".lock");
+        let lock = PathBuf::from(lock);
+
+        let file = File::create(&lock)
+            .map_err(|e| Error::OpenFile(lock.clone(), e))?;
+        file.lock()?;
+        Ok(file)
+    }
+
+    /// Copy mimeapps.list to a timestamped backup next to it, such as mimeapps.list.1700000000.bak
+    /// Existing backups are never overwritten
+    /// Returns the path of the backup, if there was anything to back up
+    #[mutants::skip] // Cannot test directly, alters system state
+    pub fn backup(&self) -> Result<Option<PathBuf>> {
+        let Some(path) = self.path.as_ref().filter(|path| path.exists()) else {
+            return Ok(None);
+        };
+
+        let time = utils::now();
+        let mut attempt = 0;
+        loop {
+            let mut backup = path.clone().into_os_string();
+            backup.push(format!(".{time}"));
+            if attempt > 0 {
+                backup.push(format!("-{attempt}"));
+            }
+            backup.push(".bak");
+            let backup = PathBuf::from(backup);
+
+            match File::options().write(true).create_new(true).open(&backup) {
+                Ok(mut file) => {
+                    std::io::copy(&mut File::open(path)?, &mut file)?;
+                    return Ok(Some(backup));
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                    attempt += 1
+                }
+                Err(e) => return Err(e.into()),
+            }
         }
     }
 
@@ -312,60 +652,149 @@ impl MimeApps {
         // Remove empty entries
         self.default_apps.retain(|_, handlers| !handlers.is_empty());
 
-        // Use Linefeed instead of default carriage return
-        let w = serde_ini::write::Writer::new(
-            writer,
-            serde_ini::write::LineEnding::Linefeed,
+        // Only touch the keys that changed, leaving the rest of the file as is
+        Self::update_section(
+            &mut self.document,
+            ADDED_ASSOCIATIONS,
+            &self.added_associations,
         );
-        let mut ser = serde_ini::ser::Serializer::new(w);
-        self.serialize(&mut ser)?;
+        Self::update_section(
+            &mut self.document,
+            DEFAULT_APPLICATIONS,
+            &self.default_apps,
+        );
+
+        writer.write_all(&self.document.to_bytes())?;
 
         Ok(())
     }
+
+    /// Get the keys of a section of a document, along with their mimes and the handlers they hold
+    fn document_entries(
+        document: &IniDocument,
+        section: &str,
+    ) -> BTreeMap<Mime, (String, DesktopList)> {
+        document
+            .entries(section)
+            .filter_map(|entry| {
+                Some((
+                    Mime::from_str(entry.key).ok()?,
+                    (
+                        entry.key.to_owned(),
+                        DesktopList::from_str(entry.value).unwrap_or_default(),
+                    ),
+                ))
+            })
+            .collect()
+    }
+
+    /// Get the changes made since mimeapps.list was read or last saved
+    pub fn changes(&self) -> Vec<MimeChange> {
+        [
+            (Section::Added, ADDED_ASSOCIATIONS),
+            (Section::Default, DEFAULT_APPLICATIONS),
+        ]
+        .into_iter()
+        .flat_map(|(section, name)| {
+            let previous = Self::document_entries(&self.document, name)
+                .into_iter()
+                .map(|(mime, (_, handlers))| (mime, handlers))
+                .collect();
+            MimeChange::between(section, &previous, self.section(section))
+        })
+        .collect()
+    }
+
+    /// Get the changes that would turn another set of associations into these ones
+    pub fn diff(&self, other: &MimeApps) -> Vec<MimeChange> {
+        [Section::Added, Section::Default]
+            .into_iter()
+            .flat_map(|section| {
+                MimeChange::between(
+                    section,
+                    other.section(section),
+                    self.section(section),
+                )
+            })
+            .collect()
+    }
+
+    /// Get the associations of a given section
+    pub fn section(&self, section: Section) -> &BTreeMap<Mime, DesktopList> {
+        match section {
+            Section::Added => &self.added_associations,
+            Section::Default => &self.default_apps,
+        }
+    }
+
+    /// Set the handlers of a given mime in a given section, removing it if there are none
+    pub fn restore(
+        &mut self,
+        section: Section,
+        mime: &Mime,
+        handlers: Option<&DesktopList>,
+    ) {
+        let associations = match section {
+            Section::Added => &mut self.added_associations,
+            Section::Default => &mut self.default_apps,
+        };
+
+        match handlers {
+            Some(handlers) => {
+                associations.insert(mime.clone(), handlers.clone());
+            }
+            None => {
+                associations.remove(mime);
+            }
+        }
+    }
+
+    /// Update a section of a document to match the given associations
+    /// Keys whose handlers did not change are left untouched, even if they are formatted differently
+    fn update_section(
+        document: &mut IniDocument,
+        section: &str,
+        associations: &BTreeMap<Mime, DesktopList>,
+    ) {
+        let existing = Self::document_entries(document, section);
+
+        for (mime, (key, handlers)) in existing.iter() {
+            match associations.get(mime) {
+                Some(new) if new == handlers => {}
+                Some(new) if !new.is_empty() => {
+                    document.set(section, key, &new.to_string())
+                }
+                // An empty entry is the same as a missing one
+                _ if handlers.is_empty() => {}
+                _ => document.remove(section, key),
+            }
+        }
+
+        associations
+            .iter()
+            .filter(|(mime, handlers)| {
+                !existing.contains_key(mime) && !handlers.is_empty()
+            })
+            .for_each(|(mime, handlers)| {
+                document.set(section, mime.as_ref(), &handlers.to_string())
+            });
+    }
 }
 
-/// Run given selector command
-#[mutants::skip] // Cannot test directly, runs external command
-fn select<O: Iterator<Item = String>>(
-    selector: &str,
-    mut opts: O,
-) -> Result<String> {
-    use std::{
-        io::prelude::*,
-        process::{Command, Stdio},
-    };
-
-    let process = {
-        let mut split = shlex::split(selector)
-            .ok_or_else(|| Error::BadCmd(selector.to_string()))?;
-        let (cmd, args) = (split.remove(0), split);
-        Command::new(cmd)
-            .args(args)
-            .stdin(Stdio::piped())
-            .stdout(Stdio::piped())
-            .spawn()?
-    };
-
-    let output = {
-        process
-            .stdin
-            .ok_or_else(|| Error::Selector(selector.to_string()))?
-            .write_all(opts.join("\n").as_bytes())?;
-
-        let mut output = String::with_capacity(24);
-
-        process
-            .stdout
-            .ok_or_else(|| Error::Selector(selector.to_string()))?
-            .read_to_string(&mut output)?;
-
-        output.trim_end().to_owned()
-    };
-
-    if output.is_empty() {
-        Err(Error::Cancelled)
-    } else {
-        Ok(output)
+/// Report the handlers of an entry that are not desktop file IDs
+fn check_handlers(entry: &Entry, diagnostics: &mut Vec<Diagnostic>) {
+    let mut offset = 0;
+
+    for handler in entry.value.split(';') {
+        if !handler.is_empty() && !handler.ends_with(".desktop") {
+            diagnostics.push(Diagnostic {
+                line: entry.line,
+                column: entry.value_column
+                    + entry.value[..offset].chars().count(),
+                message: format!("'{handler}' is not a desktop file ID"),
+            })
+        }
+        offset += handler.len() + 1;
     }
 }
 
@@ -382,7 +811,7 @@ mod tests {
         mutation: fn(&mut MimeApps) -> Result<()>,
     ) -> Result<()> {
         let file = File::open(input_path)?;
-        let mut mime_apps = MimeApps::read_from(file)?;
+        let (mut mime_apps, _) = MimeApps::read_from(file)?;
 
         mutation(&mut mime_apps)?;
 
@@ -424,33 +853,224 @@ mod tests {
 
     #[test]
     fn mimeapps_anomalous_semicolons_round_trip() -> Result<()> {
-        mimeapps_round_trip(
-            "./tests/mimeapps_anomalous_semicolons.list",
-            "./tests/mimeapps_sorted.list",
-            noop,
-        )
+        mimeapps_round_trip_simple("./tests/mimeapps_anomalous_semicolons.list")
     }
 
     #[test]
     fn mimeapps_empty_entry_round_trip() -> Result<()> {
-        mimeapps_round_trip(
-            "./tests/mimeapps_empty_entry.list",
-            "./tests/mimeapps_no_added.list",
-            noop,
-        )
+        mimeapps_round_trip_simple("./tests/mimeapps_empty_entry.list")
+    }
+
+    #[test]
+    fn mimeapps_comments_round_trip() -> Result<()> {
+        mimeapps_round_trip_simple("./tests/mimeapps_comments.list")
+    }
+
+    #[test]
+    fn mimeapps_malformed() -> Result<()> {
+        let (mut mime_apps, diagnostics) = MimeApps::read_from(File::open(
+            "./tests/mimeapps_malformed.list",
+        )?)?;
+
+        goldie::assert!(diagnostics.iter().join("\n"));
+
+        // Usable entries are still read, and duplicate sections are merged
+        assert_eq!(
+            mime_apps
+                .default_apps
+                .iter()
+                .map(|(mime, handlers)| format!("{mime}={handlers}"))
+                .collect_vec(),
+            [
+                "text/html=chromium.desktop;",
+                "text/plain=nvim;Helix.desktop;",
+                "video/mp4=mpv.desktop;\u{FFFD};",
+            ]
+        );
+        assert!(mime_apps
+            .added_associations
+            .contains_key(&Mime::from_str("x-scheme-handler/terminal")?));
+
+        // Problematic lines are kept as is
+        let mut buffer = Vec::new();
+        mime_apps.save_to(&mut buffer)?;
+        assert_eq!(buffer, std::fs::read("./tests/mimeapps_malformed.list")?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn track_changes() -> Result<()> {
+        let (mut mime_apps, _) =
+            MimeApps::read_from(File::open("./tests/mimeapps_comments.list")?)?;
+        assert!(mime_apps.changes().is_empty());
+
+        let nvim = DesktopHandler::assume_valid("nvim.desktop".into());
+        mime_apps.add_handler(&mime::TEXT_HTML, &nvim, false)?;
+        mime_apps.unset_handler(&Mime::from_str("x-scheme-handler/http")?);
+        mime_apps.set_handler(&Mime::from_str("video/mp4")?, &nvim, false)?;
+
+        assert_eq!(
+            mime_apps
+                .changes()
+                .iter()
+                .map(|c| c.to_string())
+                .collect_vec(),
+            [
+                "text/html: firefox.desktop, chromium.desktop -> firefox.desktop, chromium.desktop, nvim.desktop",
+                "video/mp4: (none) -> nvim.desktop",
+                "x-scheme-handler/http: firefox.desktop -> (none)",
+            ]
+        );
+
+        // Reverting the changes makes them go away
+        for change in mime_apps.changes() {
+            mime_apps.restore(
+                change.section,
+                &change.mime,
+                change.before.as_ref(),
+            );
+        }
+        assert!(mime_apps.changes().is_empty());
+
+        // Saving makes the current state the new baseline
+        mime_apps.set_handler(&Mime::from_str("video/mp4")?, &nvim, false)?;
+        mime_apps.save_to(&mut Vec::new())?;
+        assert!(mime_apps.changes().is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn reset_mimes() -> Result<()> {
+        let (mut mime_apps, _) =
+            MimeApps::read_from(File::open("./tests/mimeapps_comments.list")?)?;
+        let reset = |mime_apps: &mut MimeApps, pattern| {
+            mime_apps.reset(pattern);
+            mime_apps
+                .changes()
+                .iter()
+                .map(|c| format!("{:?} {}", c.section, c.mime))
+                .collect_vec()
+        };
+
+        // Globs match both sections
+        assert_eq!(
+            reset(&mut mime_apps, Some("x-scheme-handler/*")),
+            [
+                "Added x-scheme-handler/terminal",
+                "Default x-scheme-handler/http",
+                "Default x-scheme-handler/https",
+            ]
+        );
+        mime_apps.save_to(&mut Vec::new())?;
+
+        // Mimes match their aliases
+        mime_apps.add_handler(
+            &Mime::from_str("application/x-pdf")?,
+            &DesktopHandler::assume_valid("zathura.desktop".into()),
+            false,
+        )?;
+        mime_apps.save_to(&mut Vec::new())?;
+        assert_eq!(
+            reset(&mut mime_apps, Some("application/pdf")),
+            ["Default application/x-pdf"]
+        );
+        mime_apps.save_to(&mut Vec::new())?;
+
+        // Nothing matches a mime that is not set
+        assert!(reset(&mut mime_apps, Some("image/png")).is_empty());
+
+        assert_eq!(
+            reset(&mut mime_apps, None),
+            ["Default text/html", "Default text/plain"]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    // Only the entries that are modified should be rewritten
+    fn mimeapps_minimal_edits() -> Result<()> {
+        let (mut mime_apps, _) =
+            MimeApps::read_from(File::open("./tests/mimeapps_comments.list")?)?;
+
+        mime_apps.add_handler(
+            &mime::TEXT_HTML,
+            &DesktopHandler::assume_valid("nvim.desktop".into()),
+            false,
+        )?;
+        mime_apps.unset_handler(&Mime::from_str("x-scheme-handler/http")?);
+        mime_apps.set_handler(
+            &Mime::from_str("video/mp4")?,
+            &DesktopHandler::assume_valid("mpv.desktop".into()),
+            false,
+        )?;
+
+        let mut buffer = Vec::new();
+        mime_apps.save_to(&mut buffer)?;
+        goldie::assert!(String::from_utf8(buffer)?);
+
+        Ok(())
+    }
+
+    #[test]
+    // Anomalous formatting is only normalized for the entries that are modified
+    fn mimeapps_anomalous_semicolons_edit() -> Result<()> {
+        let (mut mime_apps, _) = MimeApps::read_from(File::open(
+            "./tests/mimeapps_anomalous_semicolons.list",
+        )?)?;
+
+        mime_apps.remove_handler(
+            &Mime::from_str("text/*")?,
+            &DesktopHandler::assume_valid("nvim.desktop".into()),
+        );
+
+        let mut buffer = Vec::new();
+        mime_apps.save_to(&mut buffer)?;
+        let output = String::from_utf8(buffer)?;
+
+        assert!(output.contains("\ntext/*=Helix.desktop;\n"));
+        assert!(output.contains("\nx-scheme-handler/https=firefox.desktop\n"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn unset_and_remove_report_changes() -> Result<()> {
+        let mut mime_apps = MimeApps::default();
+        let nvim = DesktopHandler::assume_valid("nvim.desktop".into());
+        let helix = DesktopHandler::assume_valid("Helix.desktop".into());
+        mime_apps.set_handler(&mime::TEXT_PLAIN, &nvim, false)?;
+
+        // Nothing to remove
+        assert!(!mime_apps.remove_handler(&mime::TEXT_PLAIN, &helix));
+        assert!(!mime_apps.remove_handler(&mime::TEXT_HTML, &nvim));
+        assert!(!mime_apps.unset_handler(&mime::TEXT_HTML));
+        assert!(!mime_apps.default_apps.contains_key(&mime::TEXT_HTML));
+
+        assert!(mime_apps.remove_handler(&Mime::from_str("text/*")?, &nvim));
+        assert!(mime_apps.unset_handler(&Mime::from_str("text/*")?));
+        assert!(mime_apps.default_apps.is_empty());
+
+        Ok(())
     }
 
     #[test]
     fn mimeapps_empty_entry_fallback() -> Result<()> {
         let file = File::open("./tests/mimeapps_empty_entry.list")?;
-        let mime_apps = MimeApps::read_from(file)?;
+        let (mime_apps, _) = MimeApps::read_from(file)?;
         let config_file = ConfigFile::default();
 
         assert_eq!(
             mime_apps
-                .get_handler_from_user(&mime::TEXT_PLAIN, &config_file)?
+                .get_handler_from_user(
+                    &mime::TEXT_PLAIN,
+                    &config_file,
+                    &UsageHistory::default()
+                )?
                 .to_string(),
-            "nvim.desktop"
+            "tests/nvim.desktop"
         );
 
         Ok(())
@@ -479,11 +1099,66 @@ mod tests {
 
     #[test]
     fn mimeapps_duplicate_round_trip() -> Result<()> {
-        mimeapps_round_trip(
-            "./tests/mimeapps_duplicate.list",
-            "./tests/mimeapps_no_added.list",
-            noop,
-        )
+        mimeapps_round_trip_simple("./tests/mimeapps_duplicate.list")
+    }
+
+    #[test]
+    fn normalize_aliases() -> Result<()> {
+        let mut mime_apps = MimeApps::default();
+
+        mime_apps.add_handler(
+            &mime::APPLICATION_PDF,
+            &DesktopHandler::assume_valid("mupdf.desktop".into()),
+            false,
+        )?;
+        mime_apps.add_handler(
+            &Mime::from_str("application/x-pdf")?,
+            &DesktopHandler::assume_valid("zathura.desktop".into()),
+            false,
+        )?;
+        mime_apps.add_handler(
+            &Mime::from_str("application/x-pdf")?,
+            &DesktopHandler::assume_valid("mupdf.desktop".into()),
+            false,
+        )?;
+        mime_apps
+            .added_associations
+            .entry(Mime::from_str("text/x-markdown")?)
+            .or_default()
+            .push_back(DesktopHandler::assume_valid("Helix.desktop".into()));
+
+        assert_eq!(
+            mime_apps.aliases_of(&mime::APPLICATION_PDF),
+            [Mime::from_str("application/x-pdf")?]
+        );
+
+        assert_eq!(
+            mime_apps.normalize_aliases(),
+            [
+                (Mime::from_str("application/x-pdf")?, mime::APPLICATION_PDF),
+                (
+                    Mime::from_str("text/x-markdown")?,
+                    Mime::from_str("text/markdown")?
+                ),
+            ]
+        );
+
+        assert!(mime_apps.aliases_of(&mime::APPLICATION_PDF).is_empty());
+        assert_eq!(
+            mime_apps.default_apps.get(&mime::APPLICATION_PDF),
+            Some(&DesktopList(
+                vec![
+                    DesktopHandler::assume_valid("mupdf.desktop".into()),
+                    DesktopHandler::assume_valid("zathura.desktop".into())
+                ]
+                .into()
+            ))
+        );
+        assert!(mime_apps
+            .added_associations
+            .contains_key(&Mime::from_str("text/markdown")?));
+
+        Ok(())
     }
 
     #[test]
@@ -593,6 +1268,125 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn reorder_handlers() -> Result<()> {
+        let mut mime_apps = MimeApps::default();
+        let [helix, nvim, kak] =
+            ["Helix.desktop", "nvim.desktop", "kak.desktop"]
+                .map(|h| DesktopHandler::assume_valid(h.into()));
+        let handlers = |mime_apps: &MimeApps, mime| -> Result<Vec<String>> {
+            Ok(mime_apps.default_apps[&Mime::from_str(mime)?]
+                .iter()
+                .map(|h| h.to_string())
+                .collect_vec())
+        };
+
+        for handler in [&helix, &nvim, &kak] {
+            mime_apps.add_handler(&mime::TEXT_PLAIN, handler, false)?;
+        }
+
+        mime_apps.reorder_handlers(
+            &mime::TEXT_PLAIN,
+            &Reorder::Promote(kak.clone()),
+        )?;
+        assert_eq!(
+            handlers(&mime_apps, "text/plain")?,
+            ["kak.desktop", "Helix.desktop", "nvim.desktop"]
+        );
+
+        mime_apps.reorder_handlers(
+            &mime::TEXT_PLAIN,
+            &Reorder::Demote(kak.clone()),
+        )?;
+        assert_eq!(
+            handlers(&mime_apps, "text/plain")?,
+            ["Helix.desktop", "nvim.desktop", "kak.desktop"]
+        );
+
+        mime_apps.reorder_handlers(
+            &mime::TEXT_PLAIN,
+            &Reorder::Order(vec![kak.clone(), nvim.clone()]),
+        )?;
+        assert_eq!(
+            handlers(&mime_apps, "text/plain")?,
+            ["kak.desktop", "nvim.desktop", "Helix.desktop"]
+        );
+
+        // Handlers that are not in the list are rejected, and the list is untouched
+        assert!(matches!(
+            mime_apps.reorder_handlers(
+                &mime::TEXT_PLAIN,
+                &Reorder::Promote(DesktopHandler::assume_valid(
+                    "mpv.desktop".into()
+                )),
+            ),
+            Err(Error::NotAssociated(..))
+        ));
+        assert_eq!(
+            handlers(&mime_apps, "text/plain")?,
+            ["kak.desktop", "nvim.desktop", "Helix.desktop"]
+        );
+
+        assert!(matches!(
+            mime_apps.reorder_handlers(
+                &mime::TEXT_HTML,
+                &Reorder::Promote(kak.clone()),
+            ),
+            Err(Error::NotFound(_))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn reorder_handlers_wildcards() -> Result<()> {
+        let mut mime_apps = MimeApps::default();
+        let [helix, nvim] = ["Helix.desktop", "nvim.desktop"]
+            .map(|h| DesktopHandler::assume_valid(h.into()));
+
+        // A literal wildcard is used for mimes that match it
+        mime_apps.add_handler(&Mime::from_str("text/*")?, &helix, false)?;
+        mime_apps.add_handler(&Mime::from_str("text/*")?, &nvim, false)?;
+
+        assert_eq!(
+            mime_apps.reorder_handlers(
+                &mime::TEXT_PLAIN,
+                &Reorder::Promote(nvim.clone())
+            )?,
+            [Mime::from_str("text/*")?]
+        );
+
+        // Mimes matching a given wildcard are all rearranged
+        mime_apps.add_handler(&mime::TEXT_HTML, &helix, false)?;
+        mime_apps.add_handler(&mime::TEXT_HTML, &nvim, false)?;
+        mime_apps.add_handler(&mime::TEXT_CSS, &helix, false)?;
+
+        assert_eq!(
+            mime_apps.reorder_handlers(
+                &Mime::from_str("text/*")?,
+                &Reorder::Demote(helix.clone())
+            )?,
+            [Mime::from_str("text/*")?]
+        );
+
+        mime_apps.default_apps.remove(&Mime::from_str("text/*")?);
+
+        // text/css does not have nvim, so it is left alone
+        assert_eq!(
+            mime_apps.reorder_handlers(
+                &Mime::from_str("text/*")?,
+                &Reorder::Promote(nvim.clone())
+            )?,
+            [mime::TEXT_HTML]
+        );
+
+        let mut buffer = Vec::new();
+        mime_apps.save_to(&mut buffer)?;
+        goldie::assert!(String::from_utf8(buffer)?);
+
+        Ok(())
+    }
+
     #[test]
     fn remove_handlers_expand_wildcards() -> Result<()> {
         let mut mime_apps = MimeApps::default();
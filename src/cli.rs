@@ -1,10 +1,12 @@
-use std::fmt::Write;
+use std::{fmt::Write, path::PathBuf};
 
 use crate::{
     apps::SystemApps,
-    common::{mime_types, DesktopHandler, MimeOrExtension, UserPath},
+    common::{
+        mime_types, DesktopHandler, LaunchTarget, MimeOrExtension, UserPath,
+    },
 };
-use clap::{builder::StyledStr, Args, Parser};
+use clap::{builder::StyledStr, Args, Parser, Subcommand, ValueEnum};
 use clap_complete::{
     engine::{ArgValueCompleter, CompletionCandidate},
     PathCompleter,
@@ -21,6 +23,42 @@ use clap_complete::{
 #[derive(Parser)]
 #[clap(disable_help_subcommand = true)]
 #[clap(version, about)]
+pub struct Cli {
+    /// Use a given mimeapps.list instead of the user's, e.g. one from a dotfiles repo
+    ///
+    /// Every command reads and modifies that file instead.
+    /// Changes to it are not recorded for `handlr undo`.
+    #[clap(
+        long,
+        global = true,
+        env = "HANDLR_MIMEAPPS",
+        value_name = "PATH",
+        add = ArgValueCompleter::new(PathCompleter::file())
+    )]
+    pub mimeapps: Option<PathBuf>,
+
+    /// Use a given directory as the root of every XDG base directory, e.g. for hermetic runs
+    ///
+    /// Config, data, state and cache are kept in `.config`, `.local/share`, `.local/state` and `.cache` inside it,
+    /// and system-wide config and data, such as installed desktop entries and the shared mime-info database,
+    /// are read from `etc/xdg` and `usr/share` inside it.
+    /// Setting the `XDG_*` environment variables directly works too.
+    #[clap(
+        long,
+        global = true,
+        env = "HANDLR_ROOT",
+        value_name = "DIR",
+        add = ArgValueCompleter::new(PathCompleter::dir())
+    )]
+    pub root: Option<PathBuf>,
+
+    #[clap(subcommand)]
+    pub command: Cmd,
+}
+
+/// Subcommands of handlr
+#[deny(missing_docs)]
+#[derive(Subcommand)]
 pub enum Cmd {
     /// List default apps and the associated handlers
     ///
@@ -57,6 +95,25 @@ pub enum Cmd {
     /// }
     ///
     /// Where each top-level key has an array with the same scheme as the normal `--json` output
+    ///
+    /// When using `--handler`, every mimetype the handler is the default for, an alternative for,
+    /// or only declares support for is listed instead. With `--json`, output will be in the form:
+    ///
+    /// [
+    ///   {
+    ///     "mime": "video/mp4",
+    ///     "role": "default"
+    ///   },
+    ///   {
+    ///     "mime": "video/webm",
+    ///     "role": "alternative"
+    ///   },
+    ///   {
+    ///     "mime": "video/x-matroska",
+    ///     "role": "declared"
+    ///   },
+    ///   ...
+    /// ]
     #[clap(verbatim_doc_comment)]
     List {
         /// Output handler info as json
@@ -65,6 +122,13 @@ pub enum Cmd {
         /// Expand wildcards in mimetypes and show global defaults
         #[clap(long, short)]
         all: bool,
+        /// Show the mimetypes of this handler instead
+        #[clap(
+            long,
+            conflicts_with = "all",
+            add = ArgValueCompleter::new(autocomplete_desktop_files)
+        )]
+        handler: Option<DesktopHandler>,
     },
 
     /// Open a path/URL with its default handler
@@ -74,6 +138,10 @@ pub enum Cmd {
     /// If multiple handlers are set and `enable_selector` is set to true,
     /// you will be prompted to select one using `selector` from ~/.config/handlr/handlr.toml.
     /// Otherwise, the default handler will be opened.
+    ///
+    /// If no handler is set and `open_with_prompt` is set to true,
+    /// you will be prompted to pick one of the installed apps instead.
+    /// If the mimetype of a file cannot be figured out, you will be prompted to pick it first.
     Open {
         /// Paths/URLs to open
         #[clap(required = true, add=ArgValueCompleter::new(PathCompleter::any()))]
@@ -91,15 +159,60 @@ pub enum Cmd {
     /// wildcards will be expanded into matching mimes rather than added verbatim
     ///
     /// File extensions are converted into their respective mimetypes in mimeapps.list.
+    /// If an extension could refer to several mimetypes (e.g. `.ts`), they are all listed,
+    /// and you will be prompted to pick one if `enable_selector` is true.
+    /// Use `--all-candidates` to set all of them instead.
+    ///
+    /// Groups of mimetypes such as `@browser` can be used to set all of them at once.
+    /// Groups can be defined under `[groups]` in `~/.config/handlr/handlr.toml`.
+    ///
+    /// Handlers can be referred to by desktop file, app name, program name or generic name (e.g. "Web Browser").
+    /// If several apps match, you will be prompted to pick one if `enable_selector` is true.
+    ///
+    /// Unknown mimetypes and handlers are rejected with suggestions for similar ones, unless `--force` is used.
+    /// A warning is printed if the handler does not declare support for the mimetype.
+    ///
+    /// If the handler is omitted, you will be prompted to pick one of the installed apps
+    /// that declare support for the mimetype, using `selector` if `enable_selector` is true
+    /// or if handlr is not running in a terminal, or a numbered list otherwise.
     ///
     /// Currently does not support regex handlers.
     Set {
-        /// Mimetype or file extension to operate on.
+        /// Mimetype, file extension or group to operate on.
         #[clap(add = ArgValueCompleter::new(autocomplete_mimes))]
         mime: MimeOrExtension,
-        /// Desktop file of handler program
+        /// Desktop file or name of handler program
+        #[clap(add = ArgValueCompleter::new(autocomplete_desktop_files))]
+        handler: Option<DesktopHandler>,
+        /// Offer every installed app when prompting, not only those that support the mimetype
+        #[clap(long, conflicts_with = "handler")]
+        all_apps: bool,
+        /// Use every mimetype an ambiguous file extension could refer to
+        #[clap(long)]
+        all_candidates: bool,
+        /// Skip checking that the mimetype and handler are known
+        #[clap(long, short)]
+        force: bool,
+        #[command(flatten)]
+        selector_args: SelectorArgs,
+    },
+
+    /// Set a handler as the default for every mimetype it declares support for
+    ///
+    /// The mimetypes are read from the `MimeType` key of the handler's desktop file.
+    /// A summary of the mimetypes whose default changed is printed.
+    ///
+    /// Currently does not support regex handlers.
+    SetDefaultApp {
+        /// Desktop file or name of handler program
         #[clap(add = ArgValueCompleter::new(autocomplete_desktop_files))]
         handler: DesktopHandler,
+        /// Only set mimetypes matching this wildcard, e.g. 'image/*'
+        #[clap(long, short)]
+        filter: Option<String>,
+        /// Only print what would change, without saving
+        #[clap(long, short)]
+        dry_run: bool,
     },
 
     /// Unset the default handler for mime/extension
@@ -111,22 +224,50 @@ pub enum Cmd {
     ///
     /// Currently does not support regex handlers.
     Unset {
-        /// Mimetype or file extension to unset the default handler of
+        /// Mimetype, file extension or group to unset the default handler of
         #[clap(add = ArgValueCompleter::new(autocomplete_mimes))]
         mime: MimeOrExtension,
+        /// Use every mimetype an ambiguous file extension could refer to
+        #[clap(long)]
+        all_candidates: bool,
+    },
+
+    /// Reset mimes back to the handlers the system would use
+    ///
+    /// Default apps and added associations matching a given mimetype or glob (e.g. `image/*`)
+    /// are removed from mimeapps.list, or every one of them with `--all`.
+    /// Aliases of a mimetype are reset along with it.
+    /// Other sections of mimeapps.list are left untouched.
+    ///
+    /// mimeapps.list is backed up next to it first, to a new file such as mimeapps.list.1700000000.bak,
+    /// and the reset can also be reverted with `handlr undo`.
+    /// The handler each reset mime now resolves to is printed.
+    Reset {
+        /// Mimetype or glob to reset
+        #[clap(
+            required_unless_present = "all",
+            add = ArgValueCompleter::new(autocomplete_mimes)
+        )]
+        mime: Option<String>,
+        /// Reset every mimetype
+        #[clap(long, conflicts_with = "mime")]
+        all: bool,
     },
 
-    /// Launch the handler for specified extension/mime with optional arguments
+    /// Launch the handler for specified extension/mime, or an app, with optional arguments
     ///
     /// Only supports wildcards for mimetypes for handlers that have been set or added with wildcards.
     ///
     /// If multiple handlers are set and `enable_selector` is set to true,
     /// you will be prompted to select one using `selector` from ~/.config/handlr/handlr.toml.
     /// Otherwise, the default handler will be opened.
+    ///
+    /// Anything that is not a mimetype or a file extension is treated as an app,
+    /// which can be referred to by name, program name or desktop file.
     Launch {
-        /// Mimetype or file extension to launch the handler of
-        #[clap(add = ArgValueCompleter::new(autocomplete_mimes))]
-        mime: MimeOrExtension,
+        /// Mimetype or file extension to launch the handler of, or app to launch
+        #[clap(add = ArgValueCompleter::new(autocomplete_launch_targets))]
+        target: LaunchTarget,
         /// Arguments to pass to handler program
         // Not necessarily a path, but completing as a path tends to be the expected "default" behavior
         #[clap(add=ArgValueCompleter::new(PathCompleter::any()))]
@@ -151,16 +292,57 @@ pub enum Cmd {
     ///   "name": "Helix"
     /// }
     ///
+    /// When given a group of mimetypes such as `@browser`, the handler of each of its mimetypes is listed instead.
+    /// With `--json`, output is in the form:
+    ///
+    /// [
+    ///   {
+    ///     "mime": "text/html",
+    ///     "handler": "firefox.desktop"
+    ///   },
+    ///   ...
+    /// ]
+    ///
     /// Note that when handlr is not being directly output to a terminal, and the handler is a terminal program,
     /// the "cmd" key in the json output will include the command of the `x-scheme-handler/terminal` handler.
+    ///
+    /// When using `--all`, every candidate handler is listed in the order they are considered,
+    /// along with where it comes from: "regex", "user default", "wildcard", "added association",
+    /// "system" or "fallback". With `--json`, output is in the form:
+    ///
+    /// [
+    ///   {
+    ///     "mime": "text/*",
+    ///     "source": "wildcard",
+    ///     "handler": "Helix.desktop",
+    ///     "name": "Helix",
+    ///     "exists": true,
+    ///     "command": "hx"
+    ///   },
+    ///   ...
+    /// ]
     #[clap(verbatim_doc_comment)]
     Get {
         /// Output handler info as json
         #[clap(long)]
         json: bool,
-        /// Mimetype to get the handler of
-        #[clap(add = ArgValueCompleter::new(autocomplete_mimes))]
-        mime: MimeOrExtension,
+        /// List every candidate handler and where it comes from
+        #[clap(long, short)]
+        all: bool,
+        /// Mimetype, file extension or group to get the handler of
+        #[clap(
+            required_unless_present = "path",
+            add = ArgValueCompleter::new(autocomplete_mimes)
+        )]
+        mime: Option<MimeOrExtension>,
+        /// Path/URL to list the candidate handlers of, including regex handlers
+        #[clap(
+            long,
+            requires = "all",
+            conflicts_with = "mime",
+            add = ArgValueCompleter::new(PathCompleter::any())
+        )]
+        path: Option<UserPath>,
         #[command(flatten)]
         selector_args: SelectorArgs,
     },
@@ -174,13 +356,25 @@ pub enum Cmd {
     ///
     /// This subcommand adds secondary handlers that coexist with the default
     /// and does not overwrite existing handlers.
+    ///
+    /// Handlers can be referred to by desktop file, app name, program name or generic name (e.g. "Web Browser").
+    /// If several apps match, you will be prompted to pick one if `enable_selector` is true.
+    ///
+    /// Unknown mimetypes and handlers are rejected with suggestions for similar ones, unless `--force` is used.
+    /// A warning is printed if the handler does not declare support for the mimetype.
     Add {
-        /// Mimetype to add handler to
+        /// Mimetype, file extension or group to add handler to
         #[clap(add = ArgValueCompleter::new(autocomplete_mimes))]
         mime: MimeOrExtension,
-        /// Desktop file of handler program
+        /// Desktop file or name of handler program
         #[clap(add = ArgValueCompleter::new(autocomplete_desktop_files))]
         handler: DesktopHandler,
+        /// Use every mimetype an ambiguous file extension could refer to
+        #[clap(long)]
+        all_candidates: bool,
+        /// Skip checking that the mimetype and handler are known
+        #[clap(long, short)]
+        force: bool,
     },
 
     /// Remove a given handler from a given mime/extension
@@ -190,12 +384,218 @@ pub enum Cmd {
     /// Literal wildcards (e.g. `text/*`) will be favored over matching mimetypes if present.
     /// Otherwise, mimes matching wildcards (e.g. `text/plain`, etc.) will have their handlers removed.
     Remove {
-        /// Mimetype to remove handler from
+        /// Mimetype, file extension or group to remove handler from
         #[clap(add = ArgValueCompleter::new(autocomplete_mimes))]
         mime: MimeOrExtension,
-        /// Desktop file of handler program to remove
+        /// Desktop file or name of handler program to remove
         #[clap(add = ArgValueCompleter::new(autocomplete_desktop_files))]
         handler: DesktopHandler,
+        /// Use every mimetype an ambiguous file extension could refer to
+        #[clap(long)]
+        all_candidates: bool,
+    },
+
+    /// Make a handler the default for a given mime/extension, keeping the others as alternatives
+    ///
+    /// The handler must already be associated with the mimetype, e.g. with `handlr add`.
+    ///
+    /// Literal wildcards (e.g. `text/*`) are used for matching mimetypes without their own handlers.
+    /// Otherwise, mimes matching wildcards (e.g. `text/plain`, etc.) that have the handler are all affected.
+    Promote {
+        /// Mimetype, file extension or group to promote handler for
+        #[clap(add = ArgValueCompleter::new(autocomplete_mimes))]
+        mime: MimeOrExtension,
+        /// Desktop file or name of handler program to promote
+        #[clap(add = ArgValueCompleter::new(autocomplete_desktop_files))]
+        handler: DesktopHandler,
+        /// Use every mimetype an ambiguous file extension could refer to
+        #[clap(long)]
+        all_candidates: bool,
+    },
+
+    /// Move a handler to the back of a given mime/extension's list of handlers
+    ///
+    /// The handler must already be associated with the mimetype.
+    /// Wildcards are handled the same way as with `handlr promote`.
+    Demote {
+        /// Mimetype, file extension or group to demote handler for
+        #[clap(add = ArgValueCompleter::new(autocomplete_mimes))]
+        mime: MimeOrExtension,
+        /// Desktop file or name of handler program to demote
+        #[clap(add = ArgValueCompleter::new(autocomplete_desktop_files))]
+        handler: DesktopHandler,
+        /// Use every mimetype an ambiguous file extension could refer to
+        #[clap(long)]
+        all_candidates: bool,
+    },
+
+    /// Rearrange the handlers of a given mime/extension
+    ///
+    /// The given handlers are moved to the front of the list in the given order,
+    /// so the first one becomes the default. Any other handlers keep their order after them.
+    ///
+    /// Every given handler must already be associated with the mimetype.
+    /// Wildcards are handled the same way as with `handlr promote`.
+    Reorder {
+        /// Mimetype, file extension or group to rearrange handlers of
+        #[clap(add = ArgValueCompleter::new(autocomplete_mimes))]
+        mime: MimeOrExtension,
+        /// Desktop files or names of handler programs, in the desired order
+        #[clap(
+            required = true,
+            add = ArgValueCompleter::new(autocomplete_desktop_files)
+        )]
+        handlers: Vec<DesktopHandler>,
+        /// Use every mimetype an ambiguous file extension could refer to
+        #[clap(long)]
+        all_candidates: bool,
+    },
+
+    /// List the changes made to mimeapps.list, newest first
+    ///
+    /// Changes made by handlr are recorded along with the command that made them,
+    /// so that they can be reverted with `handlr undo` and reapplied with `handlr redo`.
+    /// Only the last 100 changes are remembered.
+    ///
+    /// When using `--json`, output is in the form:
+    ///
+    /// [
+    ///   {
+    ///     "index": 1,
+    ///     "time": 1718000000,
+    ///     "command": "set text/plain Helix.desktop",
+    ///     "changes": ["text/plain: nvim.desktop -> Helix.desktop"],
+    ///     "undone": false
+    ///   },
+    /// ...
+    /// ]
+    ///
+    /// Where `time` is a unix timestamp.
+    #[clap(verbatim_doc_comment)]
+    History {
+        /// Output history as json
+        #[clap(long)]
+        json: bool,
+    },
+
+    /// Revert the last changes made to mimeapps.list
+    ///
+    /// Changes to mimes that have been modified since, e.g. by hand, are overwritten with a warning.
+    Undo {
+        /// Number of changes to revert
+        #[clap(default_value_t = 1)]
+        n: usize,
+    },
+
+    /// Reapply the last changes reverted with `handlr undo`
+    ///
+    /// Reverted changes can no longer be reapplied once another change is made.
+    Redo {
+        /// Number of changes to reapply
+        #[clap(default_value_t = 1)]
+        n: usize,
+    },
+
+    /// Show how the user's associations differ from the system's or another mimeapps.list
+    ///
+    /// By default, the handler used for each mime with default apps in the user's mimeapps.list,
+    /// i.e. the first one, is compared with the handler that would be used based only on the installed apps.
+    ///
+    /// With `--against`, both the default apps and added associations are compared
+    /// with those of another mimeapps.list, such as a reference copy in a dotfiles repo.
+    /// Mimes are reported as added or removed in the user's mimeapps.list relative to that file.
+    ///
+    /// When using `--json`, output is in the form:
+    ///
+    /// [
+    ///   {
+    ///     "section": "default",
+    ///     "mime": "text/plain",
+    ///     "change": "changed",
+    ///     "before": ["nvim.desktop"],
+    ///     "after": ["Helix.desktop", "nvim.desktop"]
+    ///   },
+    /// ...
+    /// ]
+    ///
+    /// Where `section` is either "default" or "added", and `change` is one of "added", "removed" or "changed".
+    #[clap(verbatim_doc_comment)]
+    Diff {
+        /// Compare with a given mimeapps.list instead of the installed apps
+        #[clap(long, add = ArgValueCompleter::new(PathCompleter::file()))]
+        against: Option<PathBuf>,
+        /// Output differences as json
+        #[clap(long)]
+        json: bool,
+    },
+
+    /// Export associations and regex handlers to a portable format
+    ///
+    /// The user's default apps and added associations from mimeapps.list are exported,
+    /// along with the regex handlers from handlr.toml, so that they can be loaded with `handlr import`.
+    ///
+    /// JSON and TOML output are in the form:
+    ///
+    /// {
+    ///   "added_associations": [
+    ///     {
+    ///       "mime": "x-scheme-handler/terminal",
+    ///       "handlers": ["org.wezfurlong.wezterm.desktop"]
+    ///     }
+    ///   ],
+    ///   "default_apps": [
+    ///     {
+    ///       "mime": "text/plain",
+    ///       "handlers": ["Helix.desktop", "nvim.desktop"]
+    ///     }
+    ///   ],
+    ///   "handlers": [
+    ///     {
+    ///       "exec": "freetube %u",
+    ///       "terminal": false,
+    ///       "regexes": ["(https://)?(www\\.)?youtu(be\\.com|\\.be)/*"]
+    ///     }
+    ///   ]
+    /// }
+    ///
+    /// Nix output is an attribute set for home-manager's `xdg.mimeApps` module.
+    /// Regex handlers are not supported by it, so they are left out.
+    #[clap(verbatim_doc_comment)]
+    Export {
+        /// Format to export to
+        #[clap(long, short, value_enum, default_value_t = ExportFormat::Json)]
+        format: ExportFormat,
+    },
+
+    /// Import associations and regex handlers exported with `handlr export`
+    ///
+    /// By default, imported associations are added to the existing ones,
+    /// replacing the handlers of any mime that is in both.
+    /// With `--merge`, the handlers of such mimes are combined instead, keeping the existing ones first.
+    /// With `--replace`, existing associations are removed first.
+    ///
+    /// Imported regex handlers are added to handlr.toml after the existing ones,
+    /// or replace them with `--replace`. Regex handlers are left alone if none are imported.
+    /// Only the `[[handlers]]` tables of handlr.toml are rewritten.
+    ///
+    /// Unknown mimetypes and desktop files are rejected, unless `--force` is used.
+    /// Changes to mimeapps.list can be reverted with `handlr undo`.
+    Import {
+        /// File to import from
+        #[clap(add = ArgValueCompleter::new(PathCompleter::file()))]
+        file: PathBuf,
+        /// Format of the file, inferred from its extension by default
+        #[clap(long, value_enum)]
+        format: Option<ExportFormat>,
+        /// Combine the handlers of mimes that are both imported and already associated
+        #[clap(long, conflicts_with = "replace")]
+        merge: bool,
+        /// Replace the existing associations
+        #[clap(long)]
+        replace: bool,
+        /// Skip checking that the mimetypes and handlers are known
+        #[clap(long, short)]
+        force: bool,
     },
 
     /// Get the mimetype of a given file/URL
@@ -224,6 +624,89 @@ pub enum Cmd {
         #[clap(long)]
         json: bool,
     },
+
+    /// List installed applications
+    ///
+    /// Shows every desktop entry found in the system's and user's data directories.
+    ///
+    /// When using `--json`, output will be in the form:
+    ///
+    /// [
+    ///   {
+    ///     "id": "Helix.desktop",
+    ///     "name": "Helix",
+    ///     "exec": "hx %F",
+    ///     "terminal": true,
+    ///     "mimes": [
+    ///       "text/plain",
+    ///       ...
+    ///     ],
+    ///     "categories": [
+    ///       "Utility",
+    ///       "TextEditor"
+    ///     ]
+    ///   },
+    /// ...
+    /// ]
+    #[clap(verbatim_doc_comment)]
+    Apps {
+        /// Only show apps whose name, generic name, comment or keywords contain this text
+        #[clap(long, short)]
+        search: Option<String>,
+        /// Only show apps that support this mimetype/extension
+        #[clap(long, short, add = ArgValueCompleter::new(autocomplete_mimes))]
+        mime: Option<MimeOrExtension>,
+        /// Output app info as json
+        #[clap(long)]
+        json: bool,
+    },
+
+    /// Rewrite aliased mimetypes in mimeapps.list to their canonical names
+    ///
+    /// For example, `application/x-pdf` is rewritten to `application/pdf`.
+    /// If both are present, handlers for the canonical mimetype come first.
+    ///
+    /// Note that `set`, `add`, `remove`, `unset` and `get` already use canonical names.
+    NormalizeAliases,
+
+    /// Show how often and how recently handlers have been used
+    ///
+    /// Every successful `open` and `launch` is recorded in `$XDG_STATE_HOME/handlr/usage.json`.
+    ///
+    /// Set `selector_order` to `recent` or `frequent` in `~/.config/handlr/handlr.toml`
+    /// to use this history to order the handlers offered by the selector.
+    ///
+    /// When using `--json`, output will be in the form:
+    ///
+    /// [
+    ///   {
+    ///     "mime": "text/plain",
+    ///     "handler": "Helix.desktop",
+    ///     "count": 12,
+    ///     "last_used": 1718000000
+    ///   },
+    /// ...
+    /// ]
+    ///
+    /// Where `last_used` is a unix timestamp.
+    #[clap(verbatim_doc_comment)]
+    Stats {
+        /// Only show usage for this mimetype/extension
+        #[clap(add = ArgValueCompleter::new(autocomplete_mimes))]
+        mime: Option<MimeOrExtension>,
+        /// Output usage info as json
+        #[clap(long)]
+        json: bool,
+    },
+}
+
+/// Formats that associations can be exported to
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum ExportFormat {
+    Json,
+    Toml,
+    /// Attribute set for home-manager
+    Nix,
 }
 
 #[derive(Clone, Args)]
@@ -254,6 +737,16 @@ fn autocomplete_mimes(current: &std::ffi::OsStr) -> Vec<CompletionCandidate> {
     mimes
 }
 
+/// Generate candidates for mimes, file extensions and desktop files
+#[mutants::skip] // Cannot test directly, relies on system state
+fn autocomplete_launch_targets(
+    current: &std::ffi::OsStr,
+) -> Vec<CompletionCandidate> {
+    let mut candidates = autocomplete_mimes(current);
+    candidates.extend(autocomplete_desktop_files(current));
+    candidates
+}
+
 /// Generate candidates for desktop files
 #[mutants::skip] // Cannot test directly, relies on system state
 fn autocomplete_desktop_files(
@@ -1,4 +1,7 @@
 use itertools::Itertools;
+use mime::Mime;
+use once_cell::sync::Lazy;
+use std::{collections::HashMap, str::FromStr};
 
 static CUSTOM_MIMES: &[&str] = &[
     "inode/directory",
@@ -19,3 +22,161 @@ pub fn mime_types() -> Vec<String> {
         )
         .collect_vec()
 }
+
+/// Mimes known by shared-mime-info on the system
+static SYSTEM_MIMES: Lazy<Vec<String>> = Lazy::new(|| {
+    xdg::BaseDirectories::new()
+        .map(|dirs| dirs.find_data_files("mime/types").collect_vec())
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|path| std::fs::read_to_string(path).ok())
+        .flat_map(|file| {
+            file.lines().map(|l| l.trim().to_owned()).collect_vec()
+        })
+        .filter(|mime| !mime.is_empty())
+        .collect_vec()
+});
+
+/// Get every known mime, both built-in and from shared-mime-info
+pub fn known_mime_types() -> Vec<String> {
+    mime_types()
+        .into_iter()
+        .chain(SYSTEM_MIMES.iter().cloned())
+        .unique()
+        .collect_vec()
+}
+
+/// Check if a given mime is known, or could reasonably exist
+/// i.e. wildcards, scheme handlers, and aliases of known mimes are always accepted
+pub fn is_known_mime(mime: &Mime) -> bool {
+    mime.as_ref().contains('*')
+        || mime.type_() == "x-scheme-handler"
+        || unalias_mime(mime) != *mime
+        || known_mime_types().iter().any(|m| m == mime.essence_str())
+}
+
+/// Shared mime-info database, loaded once on first use
+static SHARED_MIME_INFO: Lazy<xdg_mime::SharedMimeInfo> =
+    Lazy::new(xdg_mime::SharedMimeInfo::new);
+
+/// Get the canonical mime that a given mime is an alias of
+/// If it is not an alias, it is returned as is
+pub fn unalias_mime(mime: &Mime) -> Mime {
+    SHARED_MIME_INFO
+        .unalias_mime_type(mime)
+        .unwrap_or_else(|| mime.clone())
+}
+
+/// Subclass relations from shared-mime-info, mapping mimes to their direct parents
+// NOTE: `xdg_mime::SharedMimeInfo::get_parents` only works for aliases, so the files are read directly
+static SUBCLASSES: Lazy<HashMap<Mime, Vec<Mime>>> = Lazy::new(|| {
+    let mut subclasses = HashMap::<Mime, Vec<Mime>>::new();
+
+    xdg::BaseDirectories::new()
+        .map(|dirs| dirs.find_data_files("mime/subclasses").collect_vec())
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|path| std::fs::read_to_string(path).ok())
+        .for_each(|file| {
+            file.lines()
+                .filter_map(|line| line.split_whitespace().collect_tuple())
+                .filter_map(|(mime, parent)| {
+                    Some((
+                        Mime::from_str(mime).ok()?,
+                        Mime::from_str(parent).ok()?,
+                    ))
+                })
+                .for_each(|(mime, parent)| {
+                    let parents = subclasses.entry(mime).or_default();
+                    if !parents.contains(&parent) {
+                        parents.push(parent)
+                    }
+                })
+        });
+
+    subclasses
+});
+
+/// Get the direct parents of a given mime
+fn mime_parents(mime: &Mime) -> Vec<Mime> {
+    let mut parents = SUBCLASSES.get(mime).cloned().unwrap_or_default();
+
+    // All text types are implicitly subclasses of text/plain
+    if mime.type_() == mime::TEXT
+        && *mime != mime::TEXT_PLAIN
+        && !parents.contains(&mime::TEXT_PLAIN)
+    {
+        parents.push(mime::TEXT_PLAIN)
+    }
+
+    parents
+}
+
+/// Get all of the ancestors of a given mime, from most to least specific
+pub fn mime_ancestors(mime: &Mime) -> Vec<Mime> {
+    let mut ancestors: Vec<Mime> = Vec::new();
+    let mut queue = mime_parents(mime);
+
+    // Breadth-first, so that closer parents come first
+    while !queue.is_empty() {
+        let parent = queue.remove(0);
+        if parent != *mime && !ancestors.contains(&parent) {
+            queue.extend(mime_parents(&parent));
+            ancestors.push(parent);
+        }
+    }
+
+    ancestors
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn known_mimes() -> crate::error::Result<()> {
+        for mime in [
+            "text/plain",
+            "text/*",
+            "x-scheme-handler/terminal",
+            "x-scheme-handler/steam",
+            "application/x-pdf",
+        ] {
+            assert!(is_known_mime(&Mime::from_str(mime)?), "{mime}");
+        }
+
+        assert!(!is_known_mime(&Mime::from_str("text/plian")?));
+        assert!(!is_known_mime(&Mime::from_str("video/asdf")?));
+
+        Ok(())
+    }
+
+    #[test]
+    fn unalias() -> crate::error::Result<()> {
+        assert_eq!(
+            unalias_mime(&Mime::from_str("application/x-pdf")?),
+            mime::APPLICATION_PDF
+        );
+        assert_eq!(unalias_mime(&mime::TEXT_PLAIN), mime::TEXT_PLAIN);
+        Ok(())
+    }
+
+    #[test]
+    fn ancestors() -> crate::error::Result<()> {
+        assert_eq!(
+            mime_ancestors(&Mime::from_str("text/markdown")?),
+            vec![mime::TEXT_PLAIN]
+        );
+        assert_eq!(
+            mime_ancestors(&Mime::from_str("application/x-shellscript")?),
+            vec![
+                Mime::from_str("application/x-executable")?,
+                mime::TEXT_PLAIN
+            ]
+        );
+        assert!(mime_ancestors(&mime::TEXT_PLAIN).is_empty());
+
+        Ok(())
+    }
+}
@@ -1,4 +1,5 @@
 use crate::{
+    common::{mime_ancestors, unalias_mime},
     config::Config,
     error::{Error, Result},
 };
@@ -11,17 +12,24 @@ use mime::Mime;
 use once_cell::sync::Lazy;
 use std::{
     convert::TryFrom,
-    ffi::OsString,
+    ffi::{OsStr, OsString},
     path::{Path, PathBuf},
     process::{Command, Stdio},
     str::FromStr,
 };
+use wildmatch::WildMatch;
 
 /// Represents a desktop entry file for an application
 #[derive(Debug, Clone, Default, PartialEq, Eq)]
 pub struct DesktopEntry {
     /// Name of the application
     pub name: String,
+    /// Generic name of the application, e.g. "Web Browser"
+    pub generic_name: Option<String>,
+    /// Tooltip for the entry
+    pub comment: Option<String>,
+    /// Keywords that can be used to search for the entry
+    pub keywords: Vec<String>,
     /// Command to execute
     pub exec: String,
     /// Name of the desktop entry file
@@ -153,6 +161,17 @@ impl DesktopEntry {
 
         let entry = DesktopEntry {
             name: fd_entry.name(&LOCALES)?.into_owned(),
+            generic_name: fd_entry
+                .generic_name(&LOCALES)
+                .map(|n| n.into_owned()),
+            comment: fd_entry.comment(&LOCALES).map(|c| c.into_owned()),
+            keywords: fd_entry
+                .keywords(&LOCALES)
+                .unwrap_or_default()
+                .into_iter()
+                .filter(|k| !k.is_empty())
+                .map(|k| k.into_owned())
+                .collect_vec(),
             exec: fd_entry.exec()?.to_owned(),
             file_name: path.file_name()?.to_owned(),
             terminal: fd_entry.terminal(),
@@ -166,6 +185,7 @@ impl DesktopEntry {
                 .categories()
                 .unwrap_or_default()
                 .iter()
+                .filter(|c| !c.is_empty())
                 .map(|&c| c.to_owned())
                 .collect_vec(),
         };
@@ -187,6 +207,75 @@ impl DesktopEntry {
         }
     }
 
+    /// Check if the given desktop entry declares support for a given mime
+    /// Support for an alias or a parent of the mime counts too,
+    /// and a wildcard is supported if any matching mime is
+    pub fn supports_mime(&self, mime: &Mime) -> bool {
+        if mime.as_ref().contains('*') {
+            let wildcard = WildMatch::new(mime.as_ref());
+            return self.mime_type.iter().any(|m| wildcard.matches(m.as_ref()));
+        }
+
+        let supported = self.mime_type.iter().map(unalias_mime).collect_vec();
+
+        std::iter::once(unalias_mime(mime))
+            .chain(mime_ancestors(mime))
+            .any(|m| supported.contains(&m))
+            // Terminal emulators are not expected to declare this one
+            || (mime.essence_str() == "x-scheme-handler/terminal"
+                && self.is_terminal_emulator())
+    }
+
+    /// Get the file name of the program the entry runs, skipping `env` and its variables
+    pub fn exec_name(&self) -> Option<String> {
+        let exec = shlex::split(&self.exec)?;
+        let program = exec.iter().find(|word| {
+            !word.contains('=')
+                && Path::new(word).file_name().and_then(|n| n.to_str())
+                    != Some("env")
+        })?;
+
+        Some(
+            Path::new(program)
+                .file_name()?
+                .to_string_lossy()
+                .into_owned(),
+        )
+    }
+
+    /// Check how well a user-given app name refers to this entry, given its desktop ID
+    /// Lower is better: desktop ID, then name, then program, then generic name
+    /// Returns None if it does not match at all
+    pub fn name_match(&self, id: &OsStr, query: &str) -> Option<u8> {
+        let query = query.to_lowercase();
+        let matches = |s: &str| s.to_lowercase() == query;
+        let id = id.to_string_lossy();
+
+        if matches(&id) || matches(id.trim_end_matches(".desktop")) {
+            Some(0)
+        } else if matches(&self.name) {
+            Some(1)
+        } else if self.exec_name().is_some_and(|n| matches(&n)) {
+            Some(2)
+        } else if self.generic_name.as_deref().is_some_and(matches) {
+            Some(3)
+        } else {
+            None
+        }
+    }
+
+    /// Check if a given search term appears in the entry's name, generic name, comment or keywords
+    /// Case insensitive
+    pub fn matches_search(&self, search: &str) -> bool {
+        let search = search.to_lowercase();
+
+        std::iter::once(&self.name)
+            .chain(self.generic_name.iter())
+            .chain(self.comment.iter())
+            .chain(self.keywords.iter())
+            .any(|s| s.to_lowercase().contains(&search))
+    }
+
     /// Check if the given desktop entry represents a terminal emulator
     pub fn is_terminal_emulator(&self) -> bool {
         self.categories.contains(&"TerminalEmulator".to_string())
@@ -257,6 +346,75 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn supported_mimes() -> Result<()> {
+        let cmus = DesktopEntry::try_from(PathBuf::from("tests/cmus.desktop"))?;
+        assert!(cmus.supports_mime(&Mime::from_str("audio/ogg")?));
+        assert!(cmus.supports_mime(&Mime::from_str("audio/*")?));
+        assert!(!cmus.supports_mime(&Mime::from_str("video/*")?));
+        assert!(!cmus.supports_mime(&mime::TEXT_PLAIN));
+
+        let text_editor = DesktopEntry {
+            mime_type: vec![mime::TEXT_PLAIN],
+            ..Default::default()
+        };
+        assert!(text_editor.supports_mime(&Mime::from_str("text/markdown")?));
+        assert!(!text_editor.supports_mime(&mime::IMAGE_PNG));
+
+        let wezterm = DesktopEntry::try_from(PathBuf::from(
+            "tests/org.wezfurlong.wezterm.desktop",
+        ))?;
+        assert!(wezterm
+            .supports_mime(&Mime::from_str("x-scheme-handler/terminal")?));
+        assert!(
+            !cmus.supports_mime(&Mime::from_str("x-scheme-handler/terminal")?)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn match_app_names() -> Result<()> {
+        let helix =
+            DesktopEntry::try_from(PathBuf::from("tests/Helix.desktop"))?;
+        let id = OsStr::new("Helix.desktop");
+
+        assert_eq!(helix.exec_name(), Some("hx".to_string()));
+        assert_eq!(helix.name_match(id, "helix.desktop"), Some(0));
+        assert_eq!(helix.name_match(id, "Helix"), Some(0));
+        assert_eq!(
+            helix.name_match(OsStr::new("helix-editor.desktop"), "helix"),
+            Some(1)
+        );
+        assert_eq!(helix.name_match(id, "hx"), Some(2));
+        assert_eq!(helix.name_match(id, "text editor"), Some(3));
+        assert_eq!(helix.name_match(id, "vim"), None);
+
+        let env =
+            DesktopEntry::fake_entry("env FOO=bar /usr/bin/foo %U", false);
+        assert_eq!(env.exec_name(), Some("foo".to_string()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn search_entries() -> Result<()> {
+        let helix =
+            DesktopEntry::try_from(PathBuf::from("tests/Helix.desktop"))?;
+
+        assert_eq!(helix.generic_name.as_deref(), Some("Text Editor"));
+        assert_eq!(helix.comment.as_deref(), Some("Edit text files"));
+        assert_eq!(helix.keywords, ["Text", "editor"]);
+
+        assert!(helix.matches_search("HELIX"));
+        assert!(helix.matches_search("text edit"));
+        assert!(helix.matches_search("files"));
+        assert!(helix.matches_search("editor"));
+        assert!(!helix.matches_search("browser"));
+
+        Ok(())
+    }
+
     #[test]
     fn invalid_desktop_entries() -> Result<()> {
         let empty_name =
@@ -278,8 +436,8 @@ mod tests {
 
         config.terminal_output = false;
 
-        config.add_handler(
-            &Mime::from_str("x-scheme-handler/terminal")?,
+        config.add_handlers(
+            &[Mime::from_str("x-scheme-handler/terminal")?],
             &DesktopHandler::assume_valid(
                 "tests/org.wezfurlong.wezterm.desktop".into(),
             ),
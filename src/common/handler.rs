@@ -11,7 +11,7 @@ use std::{
     ffi::OsString,
     fmt::Display,
     hash::{Hash, Hasher},
-    path::PathBuf,
+    path::{Path, PathBuf},
     str::FromStr,
 };
 
@@ -64,7 +64,7 @@ impl FromStr for DesktopHandler {
 
 impl Handleable for DesktopHandler {
     fn get_entry(&self) -> Result<DesktopEntry> {
-        DesktopEntry::try_from(Self::get_path(&self.0)?)
+        DesktopEntry::try_from(self.path()?)
     }
 }
 
@@ -75,18 +75,23 @@ impl DesktopHandler {
     }
 
     /// Get the path of a given desktop entry file
+    /// Names that are already paths to a file, such as `/usr/share/applications/vim.desktop`, are used as is
     pub fn get_path(name: &std::ffi::OsStr) -> Result<PathBuf> {
-        if cfg!(test) {
-            Ok(PathBuf::from(name))
-        } else {
-            let mut path = PathBuf::from("applications");
-            path.push(name);
-            Ok(xdg::BaseDirectories::new()?
-                .find_data_file(path)
-                .ok_or_else(|| {
-                    Error::NotFound(name.to_string_lossy().into())
-                })?)
+        let given = Path::new(name);
+        if given.components().nth(1).is_some() && given.is_file() {
+            return Ok(given.to_path_buf());
         }
+
+        let mut path = PathBuf::from("applications");
+        path.push(name);
+        xdg::BaseDirectories::new()?
+            .find_data_file(path)
+            .ok_or_else(|| Error::NotFound(name.to_string_lossy().into()))
+    }
+
+    /// Get the path of the handler's desktop entry file
+    pub fn path(&self) -> Result<PathBuf> {
+        Self::get_path(&self.0)
     }
 
     /// Launch a DesktopHandler's desktop entry
@@ -97,7 +102,7 @@ impl DesktopHandler {
 }
 
 /// Represents a regex handler from the config
-#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct RegexHandler {
     exec: String,
     #[serde(default)]
@@ -122,6 +127,16 @@ impl Handleable for RegexHandler {
 #[derive(Deref, Debug, Clone, Deserialize)]
 struct RegexSet(#[serde(with = "serde_regex")] regex::RegexSet);
 
+impl Serialize for RegexSet {
+    #[mutants::skip] // Trivial
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        self.patterns().serialize(serializer)
+    }
+}
+
 #[cfg(test)]
 impl RegexSet {
     /// Create new RegexSet, currently only needed for tests
@@ -151,10 +166,38 @@ impl Hash for RegexSet {
 }
 
 /// A collection of all of the defined RegexHandlers
-#[derive(Debug, Clone, Default, Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
 pub struct RegexApps(Vec<RegexHandler>);
 
 impl RegexApps {
+    /// Whether there are no handlers
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Get the number of handlers
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Add the given handlers after the existing ones, skipping any that are already present
+    pub fn merge(&mut self, other: RegexApps) {
+        for handler in other.0 {
+            if !self.0.contains(&handler) {
+                self.0.push(handler)
+            }
+        }
+    }
+
+    /// Get every handler matching a given path, in order of precedence
+    pub fn get_handlers(&self, path: &UserPath) -> Vec<RegexHandler> {
+        self.0
+            .iter()
+            .filter(|app| app.is_match(&path.to_string()))
+            .cloned()
+            .collect()
+    }
+
     /// Get a handler matching a given path
     pub fn get_handler(&self, path: &UserPath) -> Result<RegexHandler> {
         Ok(self
@@ -205,6 +248,30 @@ mod tests {
             )?))
             .is_err());
 
+        assert_eq!(
+            regex_apps
+                .get_handlers(&UserPath::Url(Url::parse(
+                    "https://youtu.be/dQw4w9WgXcQ"
+                )?))
+                .len(),
+            1
+        );
+        assert!(regex_apps
+            .get_handlers(&UserPath::Url(Url::parse(
+                "https://en.wikipedia.org",
+            )?))
+            .is_empty());
+
+        let mut merged = regex_apps.clone();
+        merged.merge(regex_apps.clone());
+        assert_eq!(merged, regex_apps);
+        merged.merge(RegexApps(vec![RegexHandler {
+            exec: String::from("mpv %u"),
+            terminal: false,
+            regexes: RegexSet::new(regexes)?,
+        }]));
+        assert_eq!(merged.len(), 2);
+
         Ok(())
     }
 }
@@ -0,0 +1,522 @@
+use std::fmt::Display;
+
+/// What a line of an INI document contains
+#[derive(Debug, Clone, PartialEq)]
+enum LineKind {
+    /// Section header, e.g. `[Default Applications]`
+    Section(String),
+    /// Key-value pair belonging to the most recent section
+    Entry { key: String, value: String },
+    /// Anything else, e.g. comments and blank lines
+    Other,
+}
+
+/// A single line of an INI document, along with its original text
+#[derive(Debug, Clone, PartialEq)]
+struct Line {
+    text: String,
+    kind: LineKind,
+    /// Original bytes, if they were not valid UTF-8 and `text` is a lossy copy of them
+    raw: Option<Vec<u8>>,
+}
+
+impl Line {
+    /// Parse a single line, along with the column and description of any problem with it
+    /// Problematic lines are repaired if possible, and otherwise ignored
+    fn parse(text: &str) -> (Self, Option<(usize, String)>) {
+        let trimmed = text.trim();
+        let start = column(text, text.len() - text.trim_start().len());
+
+        let (kind, problem) =
+            if trimmed.is_empty() || trimmed.starts_with(['#', ';']) {
+                (LineKind::Other, None)
+            } else if let Some(header) = trimmed.strip_prefix('[') {
+                match header.strip_suffix(']') {
+                    Some(name) => (LineKind::Section(name.to_string()), None),
+                    None => {
+                        let name = header.trim().to_string();
+                        let problem = format!(
+                            "unterminated section header, assuming '[{name}]'"
+                        );
+                        (LineKind::Section(name), Some((start, problem)))
+                    }
+                }
+            } else {
+                match trimmed.split_once('=') {
+                    Some((key, _)) if key.trim().is_empty() => (
+                        LineKind::Other,
+                        Some((start, "missing key, skipping line".to_string())),
+                    ),
+                    Some((key, value)) => (
+                        LineKind::Entry {
+                            key: key.trim().to_string(),
+                            value: value.trim().to_string(),
+                        },
+                        None,
+                    ),
+                    None => (
+                        LineKind::Other,
+                        Some((
+                            start,
+                            "expected 'key=value' or '[section]', \
+                             skipping line"
+                                .to_string(),
+                        )),
+                    ),
+                }
+            };
+
+        (
+            Self {
+                text: text.to_string(),
+                kind,
+                raw: None,
+            },
+            problem,
+        )
+    }
+
+    /// Create a new key-value pair
+    fn entry(key: &str, value: &str) -> Self {
+        Self {
+            text: format!("{key}={value}"),
+            kind: LineKind::Entry {
+                key: key.to_string(),
+                value: value.to_string(),
+            },
+            raw: None,
+        }
+    }
+}
+
+/// Get the column, starting at 1, of a given byte offset in a line
+fn column(text: &str, offset: usize) -> usize {
+    text[..offset].chars().count() + 1
+}
+
+/// Problem found while parsing an INI document
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    /// Line number, starting at 1
+    pub line: usize,
+    /// Column number, starting at 1
+    pub column: usize,
+    pub message: String,
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+/// Key-value pair of an INI document, along with where it is
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Entry<'a> {
+    /// Line number, starting at 1
+    pub line: usize,
+    pub key: &'a str,
+    pub value: &'a str,
+    /// Column where the key starts, starting at 1
+    pub key_column: usize,
+    /// Column where the value starts, starting at 1
+    pub value_column: usize,
+}
+
+/// Lossless representation of an INI file such as mimeapps.list
+/// Lines that are not modified are written back exactly as they were read,
+/// including comments, unknown sections and key order
+#[derive(Debug, Clone, PartialEq)]
+pub struct IniDocument {
+    lines: Vec<Line>,
+    /// Whether the last line ends with a newline
+    trailing_newline: bool,
+}
+
+impl Default for IniDocument {
+    fn default() -> Self {
+        Self {
+            lines: Vec::new(),
+            trailing_newline: true,
+        }
+    }
+}
+
+impl IniDocument {
+    /// Parse an INI document, along with any problems found in it
+    /// Parsing never fails, problematic lines are repaired if possible and otherwise ignored,
+    /// but they are still kept as is when writing the document back
+    pub fn parse(s: &str) -> (Self, Vec<Diagnostic>) {
+        let trailing_newline = s.is_empty() || s.ends_with('\n');
+        let mut diagnostics = Vec::new();
+
+        let lines = s
+            .strip_suffix('\n')
+            .unwrap_or(s)
+            .split('\n')
+            .filter(|_| !s.is_empty())
+            .enumerate()
+            .map(|(i, text)| {
+                let (line, problem) = Line::parse(text);
+                if let Some((column, message)) = problem {
+                    diagnostics.push(Diagnostic {
+                        line: i + 1,
+                        column,
+                        message,
+                    })
+                }
+                line
+            })
+            .collect();
+
+        let document = Self {
+            lines,
+            trailing_newline,
+        };
+
+        // Check for problems that depend on the surrounding lines
+        // Sections that appear several times are merged, so keys are checked across all of them
+        let mut seen = std::collections::HashMap::new();
+        for (i, section, line) in document.lines_with_sections() {
+            let LineKind::Entry { ref key, .. } = line.kind else {
+                continue;
+            };
+            let column = column(
+                &line.text,
+                line.text.len() - line.text.trim_start().len(),
+            );
+
+            match section {
+                None => diagnostics.push(Diagnostic {
+                    line: i + 1,
+                    column,
+                    message: "entry outside of any section, skipping line"
+                        .to_string(),
+                }),
+                Some(section) => {
+                    if let Some(previous) = seen.insert((section, key), i + 1) {
+                        diagnostics.push(Diagnostic {
+                            line: i + 1,
+                            column,
+                            message: format!(
+                                "duplicate key '{key}' in section \
+                                 '[{section}]', overriding line {previous}"
+                            ),
+                        })
+                    }
+                }
+            }
+        }
+
+        diagnostics.sort_by_key(|d| (d.line, d.column));
+
+        (document, diagnostics)
+    }
+
+    /// Parse an INI document that may not be valid UTF-8, along with any problems found in it
+    /// Invalid sequences are read as U+FFFD, but their lines are written back as they were
+    pub fn parse_bytes(bytes: &[u8]) -> (Self, Vec<Diagnostic>) {
+        let (mut document, mut diagnostics) =
+            Self::parse(&String::from_utf8_lossy(bytes));
+
+        // Replacing invalid sequences never adds or removes newlines, so lines match up
+        let raw_lines = bytes.split(|&b| b == b'\n');
+        for (i, (line, raw)) in
+            document.lines.iter_mut().zip(raw_lines).enumerate()
+        {
+            if let Err(e) = std::str::from_utf8(raw) {
+                let valid = String::from_utf8_lossy(&raw[..e.valid_up_to()]);
+                diagnostics.push(Diagnostic {
+                    line: i + 1,
+                    column: valid.chars().count() + 1,
+                    message: "invalid UTF-8, reading it as U+FFFD".to_string(),
+                });
+                line.raw = Some(raw.to_vec());
+            }
+        }
+
+        diagnostics.sort_by_key(|d| (d.line, d.column));
+
+        (document, diagnostics)
+    }
+
+    /// Get the document as bytes
+    /// Unlike its text, this keeps lines that were not valid UTF-8 exactly as they were read
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+
+        for (i, line) in self.lines.iter().enumerate() {
+            if i > 0 {
+                bytes.push(b'\n');
+            }
+            bytes.extend_from_slice(
+                line.raw.as_deref().unwrap_or(line.text.as_bytes()),
+            );
+        }
+
+        if self.trailing_newline && !self.lines.is_empty() {
+            bytes.push(b'\n');
+        }
+
+        bytes
+    }
+
+    /// Get each line along with the name of the section it belongs to, if any
+    fn lines_with_sections(
+        &self,
+    ) -> impl Iterator<Item = (usize, Option<&str>, &Line)> {
+        let mut section = None;
+
+        self.lines.iter().enumerate().map(move |(i, line)| {
+            if let LineKind::Section(ref name) = line.kind {
+                section = Some(name.as_str());
+            }
+            (i, section, line)
+        })
+    }
+
+    /// Get the indices of the lines holding a given key in a given section
+    fn find(&self, section: &str, key: &str) -> Vec<usize> {
+        self.lines_with_sections()
+            .filter(|(_, s, line)| {
+                *s == Some(section)
+                    && matches!(&line.kind, LineKind::Entry { key: k, .. } if k == key)
+            })
+            .map(|(i, _, _)| i)
+            .collect()
+    }
+
+    /// Get every key-value pair in a given section, in order
+    /// If the section appears several times, all of them are included
+    pub fn entries<'a>(
+        &'a self,
+        section: &'a str,
+    ) -> impl Iterator<Item = Entry<'a>> {
+        self.lines_with_sections()
+            .filter(move |(_, s, _)| *s == Some(section))
+            .filter_map(|(i, _, line)| match line.kind {
+                LineKind::Entry { ref key, ref value } => {
+                    let text = &line.text;
+                    let key_offset = text.len() - text.trim_start().len();
+                    let equals = text.find('=').unwrap_or_default() + 1;
+                    let value_offset = equals + text[equals..].len()
+                        - text[equals..].trim_start().len();
+
+                    Some(Entry {
+                        line: i + 1,
+                        key,
+                        value,
+                        key_column: column(text, key_offset),
+                        value_column: column(text, value_offset),
+                    })
+                }
+                _ => None,
+            })
+    }
+
+    /// Set the value of a given key in a given section
+    /// Existing keys are updated in place, and new keys are added at the end of their section,
+    /// which is itself added at the end of the document if it does not exist
+    pub fn set(&mut self, section: &str, key: &str, value: &str) {
+        if let Some(&i) = self.find(section, key).last() {
+            self.lines[i] = Line::entry(key, value);
+            return;
+        }
+
+        // Insert after the last key-value pair of the section,
+        // so that any comments or blank lines before the next section stay there
+        let position = self
+            .lines_with_sections()
+            .filter(|(_, s, line)| {
+                *s == Some(section) && !matches!(line.kind, LineKind::Other)
+            })
+            .map(|(i, _, _)| i)
+            .last();
+
+        match position {
+            Some(i) => self.lines.insert(i + 1, Line::entry(key, value)),
+            None => {
+                self.lines.push(Line::parse(&format!("[{section}]")).0);
+                self.lines.push(Line::entry(key, value));
+            }
+        }
+    }
+
+    /// Remove every occurrence of a given key from a given section
+    pub fn remove(&mut self, section: &str, key: &str) {
+        for i in self.find(section, key).into_iter().rev() {
+            self.lines.remove(i);
+        }
+    }
+}
+
+impl Display for IniDocument {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, line) in self.lines.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            f.write_str(&line.text)?;
+        }
+
+        if self.trailing_newline && !self.lines.is_empty() {
+            writeln!(f)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    const DOCUMENT: &str = "\
+# Managed by dotfiles
+[Default Applications]
+text/html = firefox.desktop;
+  text/plain=nvim.desktop;
+
+; Not used by handlr
+[Removed Associations]
+image/png=gimp.desktop;
+";
+
+    #[test]
+    fn lossless_round_trip() {
+        for document in [DOCUMENT, "", "[Default Applications]", "\n\n"] {
+            let (parsed, diagnostics) = IniDocument::parse(document);
+            assert_eq!(parsed.to_string(), document);
+            assert!(diagnostics.is_empty());
+        }
+    }
+
+    #[test]
+    fn read_entries() {
+        let (document, _) = IniDocument::parse(DOCUMENT);
+        let entries = |section| {
+            document
+                .entries(section)
+                .map(|e| (e.key, e.value))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            entries("Default Applications"),
+            [
+                ("text/html", "firefox.desktop;"),
+                ("text/plain", "nvim.desktop;")
+            ]
+        );
+        assert_eq!(
+            entries("Removed Associations"),
+            [("image/png", "gimp.desktop;")]
+        );
+        assert!(entries("Added Associations").is_empty());
+
+        let html = document
+            .entries("Default Applications")
+            .next()
+            .expect("Could not get entry");
+        assert_eq!((html.line, html.key_column, html.value_column), (3, 1, 13));
+    }
+
+    #[test]
+    fn edit_entries() {
+        let (mut document, _) = IniDocument::parse(DOCUMENT);
+
+        document.set("Default Applications", "text/plain", "Helix.desktop;");
+        document.set("Default Applications", "video/mp4", "mpv.desktop;");
+        document.remove("Default Applications", "text/html");
+        document.set("Added Associations", "text/plain", "kak.desktop;");
+
+        assert_eq!(
+            document.to_string(),
+            "\
+# Managed by dotfiles
+[Default Applications]
+text/plain=Helix.desktop;
+video/mp4=mpv.desktop;
+
+; Not used by handlr
+[Removed Associations]
+image/png=gimp.desktop;
+[Added Associations]
+text/plain=kak.desktop;
+"
+        );
+    }
+
+    #[test]
+    fn invalid_utf8() {
+        let input =
+            b"[Default Applications]\ntext/plain=\xffnvim;\nvideo/mp4=mpv;\n";
+        let (mut document, diagnostics) = IniDocument::parse_bytes(input);
+
+        assert_eq!(document.to_bytes(), input);
+        assert_eq!(
+            diagnostics
+                .iter()
+                .map(|d| d.to_string())
+                .collect::<Vec<_>>(),
+            ["2:12: invalid UTF-8, reading it as U+FFFD"]
+        );
+        assert_eq!(
+            document
+                .entries("Default Applications")
+                .map(|e| e.value)
+                .collect::<Vec<_>>(),
+            ["\u{FFFD}nvim;", "mpv;"]
+        );
+
+        // Lines that are modified are written as valid UTF-8
+        document.set("Default Applications", "text/plain", "Helix.desktop;");
+        assert_eq!(
+            document.to_bytes(),
+            b"[Default Applications]\ntext/plain=Helix.desktop;\nvideo/mp4=mpv;\n"
+        );
+    }
+
+    #[test]
+    fn malformed_documents() {
+        let input = "\
+text/plain=orphan.desktop;
+[Default Applications
+text/html=firefox.desktop;
+  not an entry
+=nvim.desktop;
+[Added Associations]
+text/html=firefox.desktop;
+[Default Applications]
+text/html=chromium.desktop;
+";
+        let (document, diagnostics) = IniDocument::parse(input);
+
+        // Nothing is lost, even if it is ignored
+        assert_eq!(document.to_string(), input);
+
+        assert_eq!(
+            diagnostics.iter().map(|d| d.to_string()).collect::<Vec<_>>(),
+            [
+                "1:1: entry outside of any section, skipping line",
+                "2:1: unterminated section header, assuming '[Default Applications]'",
+                "4:3: expected 'key=value' or '[section]', skipping line",
+                "5:1: missing key, skipping line",
+                "9:1: duplicate key 'text/html' in section '[Default Applications]', overriding line 3",
+            ]
+        );
+
+        // Duplicate sections are merged
+        assert_eq!(
+            document
+                .entries("Default Applications")
+                .map(|e| (e.key, e.value))
+                .collect::<Vec<_>>(),
+            [
+                ("text/html", "firefox.desktop;"),
+                ("text/html", "chromium.desktop;")
+            ]
+        );
+    }
+}
@@ -1,7 +1,10 @@
-use crate::error::{Error, Result};
-use derive_more::Deref;
+use crate::{
+    common::DesktopHandler,
+    error::{Error, Result},
+};
+use itertools::Itertools;
 use mime::Mime;
-use std::{convert::TryFrom, path::Path, str::FromStr};
+use std::{convert::TryFrom, fmt::Display, path::Path, str::FromStr};
 use url::Url;
 
 /// A mime derived from a path or URL
@@ -9,15 +12,20 @@ use url::Url;
 pub struct MimeType(pub Mime);
 
 impl MimeType {
-    fn from_ext(ext: &str) -> Result<Mime> {
-        match &*xdg_mime::SharedMimeInfo::new()
+    /// Get every mime a given extension could refer to, best guesses first
+    fn candidates_from_ext(ext: &str) -> Result<Vec<Mime>> {
+        let candidates = xdg_mime::SharedMimeInfo::new()
             .get_mime_types_from_file_name(ext)
-        {
+            .into_iter()
+            .unique()
+            .collect_vec();
+
+        match candidates.as_slice() {
             [m] if m == &mime::APPLICATION_OCTET_STREAM => {
                 Err(Error::Ambiguous(ext.into()))
             }
-            [guess, ..] => Ok(guess.clone()),
             [] => unreachable!(),
+            _ => Ok(candidates),
         }
     }
 }
@@ -74,15 +82,41 @@ fn mime_to_option(
     }
 }
 
-/// Mime derived from user input: extension(.pdf) or type like image/jpg
-#[derive(Debug, Clone, Deref)]
-pub struct MimeOrExtension(pub Mime);
+/// Mime derived from user input: extension(.pdf), type like image/jpg or group like @browser
+#[derive(Debug, Clone)]
+pub enum MimeOrExtension {
+    Mime(Mime),
+    /// Extension that could refer to several mimes, along with all of them
+    Extension(String, Vec<Mime>),
+    /// Name of a group of mimes, which can only be resolved with the config
+    Group(String),
+}
+
+impl Display for MimeOrExtension {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Mime(mime) => write!(f, "{mime}"),
+            Self::Extension(ext, _) => f.write_str(ext),
+            Self::Group(group) => write!(f, "@{group}"),
+        }
+    }
+}
 
 impl FromStr for MimeOrExtension {
     type Err = Error;
     fn from_str(s: &str) -> Result<Self> {
-        let mime = if s.starts_with('.') {
-            MimeType::from_ext(s)?
+        let mime = if let Some(group) = s.strip_prefix('@') {
+            return Ok(Self::Group(group.to_owned()));
+        } else if s.starts_with('.') {
+            match MimeType::candidates_from_ext(s)?.as_slice() {
+                [mime] => mime.clone(),
+                candidates => {
+                    return Ok(Self::Extension(
+                        s.to_owned(),
+                        candidates.to_vec(),
+                    ))
+                }
+            }
         } else {
             match Mime::from_str(s)? {
                 m if m.subtype() == "" => return Err(Error::InvalidMime(m)),
@@ -90,7 +124,31 @@ impl FromStr for MimeOrExtension {
             }
         };
 
-        Ok(Self(mime))
+        Ok(Self::Mime(mime))
+    }
+}
+
+/// What to launch, derived from user input: either the handler of a mime/extension, or an app
+#[derive(Debug, Clone)]
+pub enum LaunchTarget {
+    Mime(MimeOrExtension),
+    App(DesktopHandler),
+}
+
+impl FromStr for LaunchTarget {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self> {
+        // Desktop entries given by ID or path, which may also contain a '/'
+        let is_app = (s.len() > ".desktop".len() && s.ends_with(".desktop"))
+            || Path::new(s).is_file();
+
+        if is_app {
+            Ok(Self::App(DesktopHandler::from_str(s)?))
+        } else if s.starts_with('.') || s.starts_with('@') || s.contains('/') {
+            Ok(Self::Mime(MimeOrExtension::from_str(s)?))
+        } else {
+            Ok(Self::App(DesktopHandler::from_str(s)?))
+        }
     }
 }
 
@@ -100,11 +158,18 @@ mod tests {
 
     #[test]
     fn user_input() -> Result<()> {
-        assert_eq!(MimeOrExtension::from_str(".pdf")?.0, mime::APPLICATION_PDF);
-        assert_eq!(
-            MimeOrExtension::from_str("image/jpeg")?.0,
-            mime::IMAGE_JPEG
-        );
+        assert!(matches!(
+            MimeOrExtension::from_str(".pdf")?,
+            MimeOrExtension::Mime(mime) if mime == mime::APPLICATION_PDF
+        ));
+        assert!(matches!(
+            MimeOrExtension::from_str("image/jpeg")?,
+            MimeOrExtension::Mime(mime) if mime == mime::IMAGE_JPEG
+        ));
+        assert!(matches!(
+            MimeOrExtension::from_str("@browser")?,
+            MimeOrExtension::Group(group) if group == "browser"
+        ));
 
         assert!("image//jpg".parse::<MimeOrExtension>().is_err());
         assert!("image".parse::<MimeOrExtension>().is_err());
@@ -112,6 +177,44 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn launch_targets() -> Result<()> {
+        assert!(matches!(
+            LaunchTarget::from_str(".pdf")?,
+            LaunchTarget::Mime(MimeOrExtension::Mime(mime)) if mime == mime::APPLICATION_PDF
+        ));
+        assert!(matches!(
+            LaunchTarget::from_str("text/plain")?,
+            LaunchTarget::Mime(MimeOrExtension::Mime(mime)) if mime == mime::TEXT_PLAIN
+        ));
+        assert!(matches!(
+            LaunchTarget::from_str("@browser")?,
+            LaunchTarget::Mime(MimeOrExtension::Group(_))
+        ));
+        assert!(matches!(
+            LaunchTarget::from_str("firefox")?,
+            LaunchTarget::App(handler) if handler.to_string() == "firefox"
+        ));
+        assert!(matches!(
+            LaunchTarget::from_str("org.mozilla.firefox.desktop")?,
+            LaunchTarget::App(_)
+        ));
+        assert!(matches!(
+            LaunchTarget::from_str("/usr/share/applications/vim.desktop")?,
+            LaunchTarget::App(handler) if handler.to_string() == "/usr/share/applications/vim.desktop"
+        ));
+        assert!(matches!(
+            LaunchTarget::from_str("tests/cat")?,
+            LaunchTarget::App(_)
+        ));
+        assert!(matches!(
+            LaunchTarget::from_str(".desktop")?,
+            LaunchTarget::Mime(_)
+        ));
+
+        Ok(())
+    }
+
     #[test]
     fn from_path() -> Result<()> {
         assert_eq!(
@@ -155,8 +258,19 @@ mod tests {
 
     #[test]
     fn from_ext() -> Result<()> {
-        assert_eq!(".mp3".parse::<MimeOrExtension>()?.0, "audio/mpeg");
-        assert_eq!("audio/mpeg".parse::<MimeOrExtension>()?.0, "audio/mpeg");
+        assert_eq!(
+            ".mp3".parse::<MimeOrExtension>()?.to_string(),
+            "audio/mpeg"
+        );
+        assert_eq!(
+            "audio/mpeg".parse::<MimeOrExtension>()?.to_string(),
+            "audio/mpeg"
+        );
+        assert!(matches!(
+            ".ts".parse::<MimeOrExtension>()?,
+            MimeOrExtension::Extension(ext, candidates)
+                if ext == ".ts" && candidates.len() > 1
+        ));
         assert!(".".parse::<MimeOrExtension>().is_err());
         assert!("audio/".parse::<MimeOrExtension>().is_err());
 
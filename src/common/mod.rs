@@ -1,15 +1,19 @@
 mod db;
 mod desktop_entry;
 mod handler;
+mod ini;
 mod mime_types;
 mod path;
 mod table;
 
-pub use self::db::mime_types;
+pub use self::db::{
+    is_known_mime, known_mime_types, mime_ancestors, mime_types, unalias_mime,
+};
 pub use desktop_entry::{DesktopEntry, Mode as ExecMode};
 pub use handler::{
     DesktopHandler, Handleable, Handler, RegexApps, RegexHandler,
 };
-pub use mime_types::{MimeOrExtension, MimeType};
+pub use ini::{Diagnostic, Entry, IniDocument};
+pub use mime_types::{LaunchTarget, MimeOrExtension, MimeType};
 pub use path::{mime_table, UserPath};
-pub use table::render_table;
+pub use table::{render_colored_table, render_table};
@@ -7,7 +7,7 @@ use serde::Serialize;
 use std::{
     convert::{TryFrom, TryInto},
     fmt::{Display, Formatter},
-    io::Write,
+    io::{Read, Write},
     path::PathBuf,
     str::FromStr,
 };
@@ -30,6 +30,31 @@ impl UserPath {
     }
 }
 
+impl UserPath {
+    /// Check if the path is a file whose beginning looks like text
+    /// i.e. it is valid UTF-8 and has no null bytes
+    pub fn looks_like_text(&self) -> bool {
+        match self {
+            Self::Url(_) => false,
+            Self::File(f) => {
+                let mut buffer = Vec::new();
+
+                std::fs::File::open(f)
+                    .and_then(|file| file.take(1024).read_to_end(&mut buffer))
+                    .is_ok_and(|_| {
+                        !buffer.contains(&0)
+                            && std::str::from_utf8(&buffer)
+                                // Allow the last character to be cut off
+                                .map_or_else(
+                                    |e| e.error_len().is_none(),
+                                    |_| true,
+                                )
+                    })
+            }
+        }
+    }
+}
+
 impl FromStr for UserPath {
     type Err = Error;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
@@ -123,6 +148,18 @@ mod tests {
         .collect()
     }
 
+    #[test]
+    fn looks_like_text() -> Result<()> {
+        assert!(UserPath::from_str("tests/rust.vim")?.looks_like_text());
+        assert!(UserPath::from_str("tests/p.html")?.looks_like_text());
+        assert!(!UserPath::from_str("tests/random.bin")?.looks_like_text());
+        assert!(!UserPath::from_str("tests/does_not_exist")?.looks_like_text());
+        assert!(
+            !UserPath::from_str("https://duckduckgo.com")?.looks_like_text()
+        );
+        Ok(())
+    }
+
     #[test]
     fn mime_table_terminal() -> Result<()> {
         let mut buffer = Vec::new();
@@ -1,25 +1,61 @@
 use tabled::{
-    settings::{themes::Colorization, Alignment, Color, Padding, Style},
+    settings::{
+        object::Rows, themes::Colorization, Alignment, Color, Padding, Style,
+    },
     Table, Tabled,
 };
 
 /// Render a table from a vector of instances of Tabled structs
 pub fn render_table<T: Tabled>(rows: &Vec<T>, terminal_output: bool) -> String {
+    build_table(rows, terminal_output).to_string()
+}
+
+/// Render a table from instances of Tabled structs, each with the color of its row
+/// Colors are only used if output is going to a terminal
+pub fn render_colored_table<T: Tabled>(
+    rows: &[(T, Color)],
+    terminal_output: bool,
+) -> String {
+    let mut table =
+        build_table(rows.iter().map(|(row, _)| row), terminal_output);
+
+    if terminal_output {
+        for (i, (_, color)) in rows.iter().enumerate() {
+            // Keep the background of every other row from the default colorization
+            let color = if i % 2 == 0 {
+                color.clone() | Color::BG_BLACK
+            } else {
+                color.clone()
+            };
+            table.modify(Rows::single(i + 1), color);
+        }
+    }
+
+    table.to_string()
+}
+
+/// Build a table styled according to where output is going
+fn build_table<I, T>(rows: I, terminal_output: bool) -> Table
+where
+    I: IntoIterator<Item = T>,
+    T: Tabled,
+{
     let mut table = Table::new(rows);
 
     if terminal_output {
         // If output is going to a terminal, print as a table
         table
             .with(Style::sharp())
-            .with(Colorization::rows([Color::FG_WHITE, Color::BG_BLACK]))
+            .with(Colorization::rows([Color::FG_WHITE, Color::BG_BLACK]));
     } else {
         // If output is being piped, print as tab-delimited text
         table
             .with(Style::empty().vertical('\t'))
             .with(Alignment::left())
-            .with(Padding::zero())
+            .with(Padding::zero());
     }
-    .to_string()
+
+    table
 }
 
 #[cfg(test)]
@@ -61,4 +97,31 @@ mod tests {
         goldie::assert!(render_table(&rows(LOREM_IPSUM), false));
         Ok(())
     }
+
+    // Helper function to create test data with a color for each row
+    fn colored_rows() -> Vec<(TestRow<'static>, Color)> {
+        rows("added mime removed mime changed mime")
+            .into_iter()
+            .zip([Color::FG_GREEN, Color::FG_RED, Color::FG_YELLOW])
+            .collect_vec()
+    }
+
+    #[test]
+    fn colored_terminal_output() -> Result<()> {
+        goldie::assert!(render_colored_table(&colored_rows(), true));
+        Ok(())
+    }
+
+    #[test]
+    fn colored_piped_output() -> Result<()> {
+        // Colors are only used for terminal output
+        assert_eq!(
+            render_colored_table(&colored_rows(), false),
+            render_table(
+                &colored_rows().into_iter().map(|(row, _)| row).collect(),
+                false
+            )
+        );
+        Ok(())
+    }
 }
@@ -1,11 +1,109 @@
 use crate::{
     cli::SelectorArgs,
-    common::{RegexApps, RegexHandler, UserPath},
+    common::{DesktopHandler, RegexApps, RegexHandler, UserPath},
     error::Result,
+    utils,
 };
+use mime::Mime;
 use serde::{Deserialize, Serialize};
+use serde_with::{serde_as, DisplayFromStr};
+use std::{collections::BTreeMap, io::Write, path::PathBuf, str::FromStr};
+
+/// Groups of mimes that are available without being defined in the config file
+static BUILTIN_GROUPS: &[(&str, &[&str])] = &[
+    (
+        "browser",
+        &[
+            "x-scheme-handler/http",
+            "x-scheme-handler/https",
+            "text/html",
+            "application/xhtml+xml",
+        ],
+    ),
+    ("mail", &["x-scheme-handler/mailto", "message/rfc822"]),
+    ("terminal", &["x-scheme-handler/terminal"]),
+    ("file-manager", &["inode/directory"]),
+    ("text-editor", &["text/plain"]),
+    (
+        "image-viewer",
+        &[
+            "image/png",
+            "image/jpeg",
+            "image/gif",
+            "image/webp",
+            "image/bmp",
+            "image/tiff",
+            "image/svg+xml",
+        ],
+    ),
+    (
+        "video-player",
+        &[
+            "video/mp4",
+            "video/webm",
+            "video/x-matroska",
+            "video/mpeg",
+            "video/quicktime",
+            "video/x-msvideo",
+        ],
+    ),
+    (
+        "audio-player",
+        &[
+            "audio/mpeg",
+            "audio/ogg",
+            "audio/x-flac",
+            "audio/mp4",
+            "audio/x-wav",
+        ],
+    ),
+    ("pdf-viewer", &["application/pdf"]),
+    (
+        "archive-manager",
+        &[
+            "application/zip",
+            "application/x-tar",
+            "application/gzip",
+            "application/x-7z-compressed",
+            "application/vnd.rar",
+        ],
+    ),
+];
+
+/// Replace the `[[handlers]]` tables of a config file with given handlers
+/// Only the handlers are touched, so comments and formatting elsewhere are kept
+fn update_handlers(contents: &str, handlers: &RegexApps) -> Result<String> {
+    #[derive(Serialize)]
+    struct Handlers<'a> {
+        handlers: &'a RegexApps,
+    }
+
+    let mut document = contents.parse::<toml_edit::DocumentMut>()?;
+
+    if handlers.is_empty() {
+        document.remove("handlers");
+    } else {
+        let mut serialized = toml::to_string(&Handlers { handlers })?
+            .parse::<toml_edit::DocumentMut>()?;
+
+        // Separate each handler from what comes before it with a blank line
+        let starts_document = document.is_empty();
+        if let Some(tables) = serialized["handlers"].as_array_of_tables_mut() {
+            for (i, table) in tables.iter_mut().enumerate() {
+                if i > 0 || !starts_document {
+                    table.decor_mut().set_prefix("\n");
+                }
+            }
+        }
+
+        document["handlers"] = serialized["handlers"].clone();
+    }
+
+    Ok(document.to_string())
+}
 
 /// The config file
+#[serde_as]
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(default)]
 pub struct ConfigFile {
@@ -17,10 +115,26 @@ pub struct ConfigFile {
     pub term_exec_args: Option<String>,
     /// Whether to expand wildcards when saving mimeapps.list
     pub expand_wildcards: bool,
+    /// How to order handlers when prompting with the selector
+    pub selector_order: HandlerOrder,
+    /// Whether to prompt for a handler with the selector when none is set
+    pub open_with_prompt: bool,
+    /// Whether to save handlers picked from the "Open with" prompt to mimeapps.list
+    pub remember_open_with: bool,
+    /// Handlers to use when no other handler can be found
+    // NOTE: Must come after other serialized fields, as it is serialized as a table
+    pub fallback: FallbackHandlers,
+    /// Named groups of mimes, in addition to the built-in ones
+    // NOTE: Must come after other serialized fields, as it is serialized as a table
+    #[serde_as(as = "BTreeMap<_, Vec<DisplayFromStr>>")]
+    pub groups: BTreeMap<String, Vec<Mime>>,
     /// Regex handlers
-    // NOTE: Serializing is only necessary for generating a default config file
-    #[serde(skip_serializing)]
+    // NOTE: Must come last, as it is serialized as an array of tables
+    #[serde(skip_serializing_if = "RegexApps::is_empty")]
     pub handlers: RegexApps,
+    /// Where the config was loaded from, if it is backed by a file
+    #[serde(skip)]
+    path: Option<PathBuf>,
 }
 
 impl Default for ConfigFile {
@@ -32,21 +146,138 @@ impl Default for ConfigFile {
             // Unfortunately, messes up emulators that don't accept it
             term_exec_args: Some("-e".into()),
             expand_wildcards: false,
+            selector_order: HandlerOrder::default(),
+            open_with_prompt: false,
+            remember_open_with: false,
+            fallback: Default::default(),
+            groups: Default::default(),
             handlers: Default::default(),
+            path: None,
         }
     }
 }
 
+/// Handlers to use for paths when the mime cannot be figured out or has no handler
+#[serde_as]
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FallbackHandlers {
+    /// Handler for files that look like text
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    pub text: Option<DesktopHandler>,
+    /// Handler for any other files, and for text files if `text` is not set
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    pub file: Option<DesktopHandler>,
+    /// Handler for URLs
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    pub url: Option<DesktopHandler>,
+}
+
+impl FallbackHandlers {
+    /// Get the fallback handler for a given path, if one is set
+    pub fn get(&self, path: &UserPath) -> Option<&DesktopHandler> {
+        match path {
+            UserPath::Url(_) => self.url.as_ref(),
+            UserPath::File(_) => self
+                .text
+                .as_ref()
+                .filter(|_| path.looks_like_text())
+                .or(self.file.as_ref()),
+        }
+    }
+}
+
+/// Orders in which handlers can be presented by the selector
+#[derive(
+    Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum HandlerOrder {
+    /// Keep the order from mimeapps.list
+    #[default]
+    Config,
+    /// Most recently used handlers first
+    Recent,
+    /// Most frequently used handlers first
+    Frequent,
+}
+
 impl ConfigFile {
     /// Get the handler associated with a given mime from the config file's regex handlers
     pub fn get_regex_handler(&self, path: &UserPath) -> Result<RegexHandler> {
         self.handlers.get_handler(path)
     }
 
-    /// Load ~/.config/handlr/handlr.toml
+    /// Get the mimes in a given group
+    /// Groups from the config file take precedence over built-in groups
+    pub fn get_group(&self, name: &str) -> Option<Vec<Mime>> {
+        self.groups.get(name).cloned().or_else(|| {
+            BUILTIN_GROUPS.iter().find(|(group, _)| *group == name).map(
+                |(_, mimes)| {
+                    mimes
+                        .iter()
+                        .map(|m| {
+                            Mime::from_str(m)
+                                .expect("Built-in mime groups should be valid")
+                        })
+                        .collect()
+                },
+            )
+        })
+    }
+
+    /// Get the names of every available group, sorted
+    pub fn group_names(&self) -> Vec<String> {
+        let mut names = self
+            .groups
+            .keys()
+            .map(String::as_str)
+            .chain(BUILTIN_GROUPS.iter().map(|(group, _)| *group))
+            .map(String::from)
+            .collect::<Vec<_>>();
+        names.sort();
+        names.dedup();
+        names
+    }
+
+    /// Load ~/.config/handlr/handlr.toml, or wherever confy keeps it on other platforms
+    /// With `--root`, it is always loaded from the relocated XDG config directory
     #[mutants::skip] // Cannot test directly, depends on system state
     pub fn load() -> Result<Self> {
-        Ok(confy::load("handlr")?)
+        let path = if utils::xdg_relocated() {
+            xdg::BaseDirectories::with_prefix("handlr")?
+                .place_config_file("handlr.toml")?
+        } else {
+            // Where `confy::load("handlr")` looks
+            directories::ProjectDirs::from("rs", "", "handlr")
+                .ok_or(confy::ConfyError::BadConfigDirectoryStr)?
+                .config_dir()
+                .join("handlr.toml")
+        };
+
+        let mut config: Self = confy::load_path(&path)?;
+        config.path = Some(path);
+
+        Ok(config)
+    }
+
+    /// Save the regex handlers to the loaded handlr.toml, keeping the rest of it as is
+    /// Config that is not backed by a file is left alone
+    #[mutants::skip] // Cannot test directly, alters system state
+    pub fn save_handlers(&self) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+
+        let contents = match std::fs::read_to_string(path) {
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            contents => contents?,
+        };
+
+        let contents = update_handlers(&contents, &self.handlers)?;
+        utils::write_atomic(path, |file| {
+            Ok(file.write_all(contents.as_bytes())?)
+        })
     }
 
     /// Override the set selector
@@ -61,3 +292,57 @@ impl ConfigFile {
             && !selector_args.disable_selector;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::mime_types;
+    use pretty_assertions::assert_eq;
+
+    const CONFIG: &str = r#"# Managed by dotfiles
+enable_selector = true # Pick with rofi
+selector = "rofi -dmenu -i -p 'Open With: '"
+
+[[handlers]]
+exec = "freetube %u"
+regexes = ["youtu\\.be/.*"]
+"#;
+
+    #[test]
+    fn builtin_groups_are_known_mimes() {
+        let known = mime_types();
+
+        let unknown = BUILTIN_GROUPS
+            .iter()
+            .flat_map(|(group, mimes)| mimes.iter().map(move |m| (group, m)))
+            .filter(|(_, mime)| {
+                !mime.starts_with("x-scheme-handler/")
+                    && !known.iter().any(|m| m == *mime)
+            })
+            .map(|(group, mime)| format!("@{group}: {mime}"))
+            .collect::<Vec<_>>();
+
+        assert_eq!(unknown, Vec::<String>::new());
+    }
+
+    #[test]
+    fn update_handlers_keeps_the_rest() -> Result<()> {
+        let imported: ConfigFile = toml::from_str(
+            r#"
+            [[handlers]]
+            exec = "mpv %u"
+            regexes = ["twitch\\.tv/.*"]
+            "#,
+        )?;
+
+        goldie::assert!(update_handlers(CONFIG, &imported.handlers)?);
+
+        // Removing every handler leaves nothing behind
+        assert_eq!(
+            update_handlers(CONFIG, &RegexApps::default())?,
+            "# Managed by dotfiles\nenable_selector = true # Pick with rofi\nselector = \"rofi -dmenu -i -p 'Open With: '\"\n"
+        );
+
+        Ok(())
+    }
+}
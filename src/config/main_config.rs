@@ -1,20 +1,32 @@
 use mime::Mime;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use std::{
     collections::{BTreeMap, HashMap, VecDeque},
+    ffi::OsString,
     io::{IsTerminal, Write},
+    path::{Path, PathBuf},
     str::FromStr,
 };
-use tabled::Tabled;
+use tabled::{settings::Color, Tabled};
 
 use crate::{
-    apps::{DesktopList, MimeApps, SystemApps},
-    cli::SelectorArgs,
-    common::{render_table, DesktopHandler, Handleable, Handler, UserPath},
+    apps::{
+        ChangeKind, DesktopList, Journal, MimeApps, MimeChange, Reorder,
+        Section, SystemApps, UsageHistory,
+    },
+    cli::{ExportFormat, SelectorArgs},
+    common::{
+        is_known_mime, known_mime_types, mime_ancestors, mime_types,
+        render_colored_table, render_table, unalias_mime, DesktopEntry,
+        DesktopHandler, Handleable, Handler, LaunchTarget, MimeOrExtension,
+        RegexApps, UserPath,
+    },
     config::config_file::ConfigFile,
     error::{Error, Result},
     utils,
 };
+use itertools::Itertools;
+use wildmatch::WildMatch;
 
 /// A single struct that holds all apps and config.
 /// Used to streamline explicitly passing state.
@@ -26,13 +38,22 @@ pub struct Config {
     system_apps: SystemApps,
     /// Handlr-specific config file
     config: ConfigFile,
+    /// Record of which handlers have been used to open which mimes
+    usage: UsageHistory,
+    /// Record of changes made to mimeapps.list
+    journal: Journal,
     /// Whether or not stdout is a terminal
     pub terminal_output: bool,
 }
 
 impl Config {
     /// Create a new instance of AppsConfig
-    pub fn new() -> Result<Self> {
+    /// Uses a given mimeapps.list instead of the user's if there is one,
+    /// which is only created if it does not exist and `create_mimeapps` is true
+    pub fn new(
+        mimeapps: Option<PathBuf>,
+        create_mimeapps: bool,
+    ) -> Result<Self> {
         let config = ConfigFile::load();
         let terminal_output = std::io::stdout().is_terminal();
 
@@ -44,18 +65,60 @@ impl Config {
             }
         }
 
+        // Changes to another mimeapps.list must not end up in the user's journal,
+        // so they are only kept in memory
+        let journal = match mimeapps {
+            Some(_) => Journal::default(),
+            // Likewise, the journal should not get in the way of changing associations
+            None => Journal::read().unwrap_or_default(),
+        };
+
         Ok(Self {
             // Ensure fields individually default rather than making the whole thing fail if one is missing
-            mime_apps: MimeApps::read()?,
+            mime_apps: MimeApps::read(mimeapps, create_mimeapps)?,
             system_apps: SystemApps::populate()?,
             config: config?,
+            // Usage history is non-essential, so do not fail if it cannot be read
+            usage: UsageHistory::read().unwrap_or_default(),
+            journal,
             terminal_output,
         })
     }
 
     /// Get the handler associated with a given mime
+    /// If there is none, the mime's canonical name and its parents are tried in turn,
+    /// from most to least specific, as described in the xdg spec
     pub fn get_handler(&self, mime: &Mime) -> Result<DesktopHandler> {
-        match self.mime_apps.get_handler_from_user(mime, &self.config) {
+        Ok(self.get_handler_and_source(mime)?.0)
+    }
+
+    /// Get the handler associated with a given mime,
+    /// along with the mime it was found through, e.g. one of its parents
+    fn get_handler_and_source(
+        &self,
+        mime: &Mime,
+    ) -> Result<(DesktopHandler, Mime)> {
+        let unaliased = unalias_mime(mime);
+
+        std::iter::once(mime.clone())
+            .chain((unaliased != *mime).then(|| unaliased.clone()))
+            .chain(mime_ancestors(&unaliased))
+            .map(|m| Ok((self.get_handler_without_fallback(&m)?, m)))
+            .find(|h| !matches!(h, Err(Error::NotFound(_))))
+            .unwrap_or_else(|| Err(Error::NotFound(mime.to_string())))
+    }
+
+    /// Get the handler associated with exactly the given mime,
+    /// without trying its canonical name or parents
+    fn get_handler_without_fallback(
+        &self,
+        mime: &Mime,
+    ) -> Result<DesktopHandler> {
+        match self.mime_apps.get_handler_from_user(
+            mime,
+            &self.config,
+            &self.usage,
+        ) {
             Err(e) if matches!(e, Error::Cancelled) => Err(e),
             h => h.or_else(|_| self.get_handler_from_added_associations(mime)),
         }
@@ -68,8 +131,7 @@ impl Config {
         mime: &Mime,
     ) -> Result<DesktopHandler> {
         self.mime_apps
-            .added_associations
-            .get(mime)
+            .get_with_aliases(&self.mime_apps.added_associations, mime)
             .map_or_else(
                 || self.system_apps.get_handler(mime),
                 |h| h.front().cloned(),
@@ -77,11 +139,318 @@ impl Config {
             .ok_or_else(|| Error::NotFound(mime.to_string()))
     }
 
+    /// Get the handler associated with a given mime, along with the mime it was found through
+    /// If there is none, use the fallback handler for the given path if one is set
+    /// or prompt the user to pick one if `open_with_prompt` is enabled
+    fn get_handler_or_fallback(
+        &mut self,
+        mime: &Mime,
+        path: Option<&UserPath>,
+    ) -> Result<(DesktopHandler, Mime)> {
+        match self.get_handler_and_source(mime) {
+            Err(Error::NotFound(m)) => {
+                match path.and_then(|p| self.config.fallback.get(p)).cloned() {
+                    Some(fallback) => Ok((fallback, mime.clone())),
+                    None if self.config.open_with_prompt => {
+                        let handler = self.prompt_handler(mime, true)?;
+
+                        if self.config.remember_open_with {
+                            self.remember_handler(mime, &handler)?;
+                        }
+
+                        Ok((handler, mime.clone()))
+                    }
+                    None => Err(Error::NotFound(m)),
+                }
+            }
+            handler => handler,
+        }
+    }
+
+    /// Save a handler picked to open a given mime as its default
+    /// mimeapps.list was read without a lock, so it is read again under one
+    /// in case another process changed it while the user was picking
+    #[mutants::skip] // Cannot test directly, alters system state
+    fn remember_handler(
+        &mut self,
+        mime: &Mime,
+        handler: &DesktopHandler,
+    ) -> Result<()> {
+        let _lock = self.mime_apps.lock_and_reload()?;
+        self.journal.reload()?;
+
+        self.mime_apps.set_handler(mime, handler, false)?;
+        self.save_mime_apps()
+    }
+
+    /// Get the candidates for picking a handler for a given mime
+    /// Apps associated with the mime or its parents come first,
+    /// followed by every other app if `all_apps` is true
+    fn handler_candidates(
+        &self,
+        mime: &Mime,
+        all_apps: bool,
+    ) -> Vec<(DesktopHandler, String)> {
+        let all_handlers = if all_apps {
+            self.system_apps.get_all_handlers()
+        } else {
+            Default::default()
+        };
+
+        self.system_apps
+            .get_handlers_with_fallback(mime)
+            .iter()
+            .chain(all_handlers.iter())
+            .unique()
+            .filter_map(|h| Some((h.clone(), h.get_entry().ok()?.name)))
+            .collect_vec()
+    }
+
+    /// Prompt the user to pick one of the given options
+    /// Uses the selector if it is enabled or if handlr is not running in a terminal,
+    /// otherwise uses a numbered list in the terminal
+    #[mutants::skip] // Cannot test directly, runs external command or reads from stdin
+    fn pick(&self, options: &[String]) -> Result<String> {
+        if self.config.enable_selector || !self.terminal_output {
+            utils::select(&self.config.selector, options.iter().cloned())
+        } else {
+            utils::pick_in_terminal(options)
+        }
+    }
+
+    /// Prompt the user to pick a handler for a given mime
+    #[mutants::skip] // Cannot test directly, runs external command or reads from stdin
+    fn prompt_handler(
+        &self,
+        mime: &Mime,
+        all_apps: bool,
+    ) -> Result<DesktopHandler> {
+        let candidates = self.handler_candidates(mime, all_apps);
+
+        if candidates.is_empty() {
+            return Err(Error::NotFound(mime.to_string()));
+        }
+
+        self.pick_handler(&candidates)
+    }
+
+    /// Prompt the user to pick one of the given handlers, labelled by their names
+    #[mutants::skip] // Cannot test directly, runs external command or reads from stdin
+    fn pick_handler(
+        &self,
+        candidates: &[(DesktopHandler, String)],
+    ) -> Result<DesktopHandler> {
+        let labels = candidates
+            .iter()
+            .map(|(handler, name)| format!("{name} ({handler})"))
+            .collect_vec();
+        let choice = self.pick(&labels)?;
+
+        labels
+            .iter()
+            .position(|label| *label == choice)
+            .map(|i| candidates[i].0.clone())
+            .ok_or(Error::NotFound(choice))
+    }
+
+    /// Resolve an app name, program name or desktop ID into a desktop handler
+    #[mutants::skip] // Cannot test directly, depends on system state
+    pub fn resolve_handler(
+        &self,
+        handler: &DesktopHandler,
+    ) -> Result<DesktopHandler> {
+        let name = handler.to_string();
+
+        // Already a desktop ID or path, no need to look through every app
+        if name.ends_with(".desktop") || name.contains('/') {
+            Ok(handler.clone())
+        } else {
+            self.resolve_handler_from(handler, SystemApps::get_entries()?)
+        }
+    }
+
+    /// Resolve a handler against given desktop entries
+    /// Handlers that match nothing are returned as is
+    /// If several entries match equally well, the user is prompted if the selector is enabled
+    fn resolve_handler_from<I>(
+        &self,
+        handler: &DesktopHandler,
+        entries: I,
+    ) -> Result<DesktopHandler>
+    where
+        I: IntoIterator<Item = (OsString, DesktopEntry)>,
+    {
+        let name = handler.to_string();
+
+        let matches = entries
+            .into_iter()
+            .filter_map(|(id, entry)| {
+                Some((entry.name_match(&id, &name)?, id, entry.name))
+            })
+            .min_set_by_key(|(rank, _, _)| *rank)
+            .into_iter()
+            .map(|(_, id, name)| (DesktopHandler::assume_valid(id), name))
+            .sorted()
+            .collect_vec();
+
+        match matches.as_slice() {
+            [] => Ok(handler.clone()),
+            [(handler, _)] => Ok(handler.clone()),
+            _ if self.config.enable_selector => self.pick_handler(&matches),
+            _ => Err(Error::AmbiguousHandler(
+                name,
+                matches
+                    .iter()
+                    .map(|(handler, name)| format!("{name} ({handler})"))
+                    .collect_vec(),
+            )),
+        }
+    }
+
+    /// Prompt the user to pick a mime
+    #[mutants::skip] // Cannot test directly, runs external command or reads from stdin
+    fn prompt_mime(&self) -> Result<Mime> {
+        Ok(Mime::from_str(
+            &self.pick(&mime_types().into_iter().sorted().collect_vec())?,
+        )?)
+    }
+
+    /// Get the mimes that user input refers to, resolving groups
+    /// Extensions that could refer to several mimes resolve to all of them if `all_candidates` is true,
+    /// otherwise the user is prompted to pick one if the selector is enabled
+    pub fn resolve_mimes(
+        &self,
+        mime: &MimeOrExtension,
+        all_candidates: bool,
+    ) -> Result<Vec<Mime>> {
+        match mime {
+            MimeOrExtension::Mime(mime) => Ok(vec![mime.clone()]),
+            MimeOrExtension::Extension(_, candidates) if all_candidates => {
+                Ok(candidates.clone())
+            }
+            MimeOrExtension::Extension(ext, candidates) => {
+                let candidates =
+                    candidates.iter().map(|m| m.to_string()).collect_vec();
+
+                if self.config.enable_selector {
+                    Ok(vec![Mime::from_str(&self.pick(&candidates)?)?])
+                } else {
+                    Err(Error::AmbiguousExtension(ext.clone(), candidates))
+                }
+            }
+            MimeOrExtension::Group(group) => match self.config.get_group(group)
+            {
+                Some(mimes) if mimes.is_empty() => {
+                    Err(Error::EmptyGroup(group.clone()))
+                }
+                Some(mimes) => Ok(mimes),
+                None => Err(Error::UnknownGroup(
+                    group.clone(),
+                    utils::did_you_mean(group, self.config.group_names()),
+                )),
+            },
+        }
+    }
+
+    /// Set the default handler for a mime, extension or group,
+    /// checking that they are known unless forced
+    /// If no handler is given, the user is prompted to pick one
+    /// that supports the first mime
+    #[mutants::skip] // Cannot test directly, depends on system state
+    pub fn set_handler_checked(
+        &mut self,
+        mime: &MimeOrExtension,
+        handler: Option<&DesktopHandler>,
+        all_apps: bool,
+        all_candidates: bool,
+        force: bool,
+    ) -> Result<()> {
+        let mimes = self.resolve_mimes(mime, all_candidates)?;
+        mimes.iter().try_for_each(|m| self.check_mime(m, force))?;
+
+        let handler = match handler {
+            Some(handler) => {
+                let handler = self.resolve_handler(handler)?;
+                mimes
+                    .iter()
+                    .try_for_each(|m| self.check_handler(m, &handler, force))?;
+                handler
+            }
+            None => {
+                let first =
+                    mimes.first().ok_or(Error::NotFound(mime.to_string()))?;
+                self.prompt_handler(first, all_apps)?
+            }
+        };
+
+        self.set_handlers(&mimes, &handler)
+    }
+
+    /// Add a handler for a mime, extension or group,
+    /// checking that they are known unless forced
+    #[mutants::skip] // Cannot test directly, depends on system state
+    pub fn add_handler_checked(
+        &mut self,
+        mime: &MimeOrExtension,
+        handler: &DesktopHandler,
+        all_candidates: bool,
+        force: bool,
+    ) -> Result<()> {
+        let mimes = self.resolve_mimes(mime, all_candidates)?;
+        mimes.iter().try_for_each(|m| self.check_mime(m, force))?;
+
+        let handler = self.resolve_handler(handler)?;
+        mimes
+            .iter()
+            .try_for_each(|m| self.check_handler(m, &handler, force))?;
+
+        self.add_handlers(&mimes, &handler)
+    }
+
     /// Given a mime and arguments, launch the associated handler with the arguments
     #[mutants::skip] // Cannot test directly, runs external command
-    pub fn launch_handler(&self, mime: &Mime, args: Vec<String>) -> Result<()> {
-        self.get_handler(mime)?
-            .launch(self, args.into_iter().map(|a| a.to_string()).collect())
+    pub fn launch_handler(
+        &mut self,
+        mime: &Mime,
+        args: Vec<String>,
+    ) -> Result<()> {
+        let (handler, source) = self.get_handler_or_fallback(mime, None)?;
+        handler
+            .launch(self, args.into_iter().map(|a| a.to_string()).collect())?;
+
+        self.usage.record(&source, &handler);
+        self.save_usage();
+
+        Ok(())
+    }
+
+    /// Launch the handler of a given mime or a given app, with optional arguments
+    #[mutants::skip] // Cannot test directly, runs external command
+    pub fn launch(
+        &mut self,
+        target: &LaunchTarget,
+        args: Vec<String>,
+    ) -> Result<()> {
+        match target {
+            LaunchTarget::Mime(mime) => {
+                // Launching cannot use every candidate of an extension at once
+                let mimes = match self.resolve_mimes(mime, false) {
+                    Err(Error::AmbiguousExtension(ext, candidates)) => {
+                        return Err(Error::AmbiguousLaunch(ext, candidates))
+                    }
+                    mimes => mimes?,
+                };
+
+                // Any mime in a group will do, so use the first one
+                match mimes.first() {
+                    Some(mime) => self.launch_handler(mime, args),
+                    None => Err(Error::NotFound(mime.to_string())),
+                }
+            }
+            LaunchTarget::App(handler) => {
+                self.resolve_handler(handler)?.launch(self, args)
+            }
+        }
     }
 
     /// Get the handler associated with a given mime
@@ -91,7 +460,7 @@ impl Config {
         mime: &Mime,
         output_json: bool,
     ) -> Result<()> {
-        let handler = self.get_handler(mime)?;
+        let handler = self.get_handler(&canonicalize(mime))?;
 
         let output = if output_json {
             let entry = handler.get_entry()?;
@@ -110,71 +479,405 @@ impl Config {
         Ok(())
     }
 
-    /// Set a default application association, overwriting any existing association for the same mimetype
-    /// and writes it to mimeapps.list
-    pub fn set_handler(
-        &mut self,
+    /// Get every candidate handler for a given mime or path, in the order they are considered
+    /// Parents and the canonical name of the mime are considered too,
+    /// as are regex and fallback handlers for paths
+    fn handler_candidates_with_sources(
+        &self,
+        mime: &Mime,
+        path: Option<&UserPath>,
+    ) -> Vec<CandidateEntry> {
+        let mut candidates = Vec::new();
+
+        if let Some(path) = path {
+            for handler in self.config.handlers.get_handlers(path) {
+                candidates.push(CandidateEntry::new(
+                    self,
+                    "",
+                    CandidateSource::Regex,
+                    &handler.into(),
+                ));
+            }
+        }
+
+        let unaliased = unalias_mime(mime);
+
+        for mime in std::iter::once(mime.clone())
+            .chain((unaliased != *mime).then(|| unaliased.clone()))
+            .chain(mime_ancestors(&unaliased))
+        {
+            let mut push = |key: &Mime, source, handlers: &DesktopList| {
+                for handler in handlers.iter() {
+                    candidates.push(CandidateEntry::new(
+                        self,
+                        key.as_ref(),
+                        source,
+                        &handler.clone().into(),
+                    ))
+                }
+            };
+
+            if let Some(handlers) = self
+                .mime_apps
+                .get_with_aliases(&self.mime_apps.default_apps, &mime)
+            {
+                push(&mime, CandidateSource::Default, handlers)
+            }
+
+            if let Some((wildcard, handlers)) =
+                self.mime_apps.get_wildcard_match(&mime)
+            {
+                push(wildcard, CandidateSource::Wildcard, handlers)
+            }
+
+            if let Some(handlers) = self
+                .mime_apps
+                .get_with_aliases(&self.mime_apps.added_associations, &mime)
+            {
+                push(&mime, CandidateSource::Added, handlers)
+            }
+
+            if let Some(handlers) = self.system_apps.get_handlers(&mime) {
+                push(&mime, CandidateSource::System, &handlers)
+            }
+        }
+
+        if let Some(fallback) = path.and_then(|p| self.config.fallback.get(p)) {
+            candidates.push(CandidateEntry::new(
+                self,
+                "",
+                CandidateSource::Fallback,
+                &fallback.clone().into(),
+            ))
+        }
+
+        // The same wildcard can match both a mime and its ancestors
+        candidates
+            .into_iter()
+            .unique_by(|c| (c.mime.clone(), c.source, c.handler.clone()))
+            .collect_vec()
+    }
+
+    /// Print every candidate handler for a given mime, extension, group or path,
+    /// in the order they are considered
+    pub fn show_handler_candidates<W: Write>(
+        &self,
+        writer: &mut W,
+        mime: Option<&MimeOrExtension>,
+        path: Option<&UserPath>,
+        output_json: bool,
+    ) -> Result<()> {
+        let rows = match (mime, path) {
+            (Some(mime), _) => self
+                .resolve_mimes(mime, true)?
+                .iter()
+                .flat_map(|m| self.handler_candidates_with_sources(m, None))
+                .collect_vec(),
+            (None, Some(path)) => {
+                // Regex handlers only depend on the path,
+                // so they still apply if the mime cannot be figured out
+                let mime = match path.get_mime() {
+                    Err(Error::Ambiguous(_)) => mime::APPLICATION_OCTET_STREAM,
+                    mime => mime?,
+                };
+                self.handler_candidates_with_sources(&mime, Some(path))
+            }
+            (None, None) => Vec::new(),
+        };
+
+        let table = if output_json {
+            serde_json::to_string(&rows)?
+        } else {
+            render_table(&rows, self.terminal_output)
+        };
+
+        writeln!(writer, "{table}")?;
+
+        Ok(())
+    }
+
+    /// Get the handlers associated with the mimes of a group or ambiguous extension
+    /// Mimes without a handler are left out
+    pub fn show_group_handlers<W: Write>(
+        &self,
+        writer: &mut W,
+        group: &MimeOrExtension,
+        output_json: bool,
+    ) -> Result<()> {
+        let mut rows = Vec::new();
+
+        for mime in self.resolve_mimes(group, true)? {
+            match self.get_handler(&canonicalize(&mime)) {
+                Ok(handler) => rows.push(GroupHandlerEntry {
+                    mime: mime.to_string(),
+                    handler: handler.to_string(),
+                }),
+                Err(Error::NotFound(_)) => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        if rows.is_empty() {
+            return Err(Error::NotFound(group.to_string()));
+        }
+
+        let table = if output_json {
+            serde_json::to_string(&rows)?
+        } else {
+            render_table(&rows, self.terminal_output)
+        };
+
+        writeln!(writer, "{table}")?;
+
+        Ok(())
+    }
+
+    /// Check that a given mime is known, suggesting similar known mimes if it is not
+    /// Always succeeds when forced
+    pub fn check_mime(&self, mime: &Mime, force: bool) -> Result<()> {
+        if force || is_known_mime(mime) {
+            Ok(())
+        } else {
+            Err(Error::UnknownMime(
+                mime.clone(),
+                utils::did_you_mean(mime.as_ref(), known_mime_types()),
+            ))
+        }
+    }
+
+    /// Check that a given handler exists and can be parsed, suggesting similar handlers if it does not
+    /// Also warns if the handler does not declare support for the given mime
+    /// Always succeeds when forced
+    pub fn check_handler(
+        &self,
         mime: &Mime,
         handler: &DesktopHandler,
+        force: bool,
     ) -> Result<()> {
-        self.mime_apps.set_handler(
-            mime,
-            handler,
-            self.config.expand_wildcards,
-        )?;
-        self.mime_apps.save()
+        if force {
+            return Ok(());
+        }
+
+        self.check_handler_exists(handler)?;
+
+        if !handler.get_entry()?.supports_mime(mime) {
+            utils::warn(&format!(
+                "'{handler}' does not declare support for '{mime}'"
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Check that a given handler's desktop entry exists,
+    /// suggesting similarly named ones if it does not
+    fn check_handler_exists(&self, handler: &DesktopHandler) -> Result<()> {
+        let unknown = || {
+            Error::UnknownHandler(
+                handler.clone(),
+                utils::did_you_mean(
+                    &handler.to_string(),
+                    self.system_apps
+                        .get_all_handlers()
+                        .iter()
+                        .map(|h| h.to_string()),
+                ),
+            )
+        };
+
+        match handler.path() {
+            Ok(path) if path.exists() => Ok(()),
+            Ok(_) | Err(Error::NotFound(_)) => Err(unknown()),
+            Err(e) => Err(e),
+        }
     }
 
-    /// Add a handler to an existing default application association
-    /// and writes it to mimeapps.list
-    pub fn add_handler(
+    /// Set a default application association for each given mime, overwriting any existing one,
+    /// and write them all to mimeapps.list at once
+    pub fn set_handlers(
         &mut self,
-        mime: &Mime,
+        mimes: &[Mime],
         handler: &DesktopHandler,
     ) -> Result<()> {
-        self.mime_apps.add_handler(
-            mime,
+        for mime in mimes {
+            self.mime_apps.set_handler(
+                &canonicalize(mime),
+                handler,
+                self.config.expand_wildcards,
+            )?;
+        }
+        self.save_mime_apps()
+    }
+
+    /// Add a handler to the existing default application association of each given mime
+    /// and write them all to mimeapps.list at once
+    pub fn add_handlers(
+        &mut self,
+        mimes: &[Mime],
+        handler: &DesktopHandler,
+    ) -> Result<()> {
+        for mime in mimes {
+            self.mime_apps.add_handler(
+                &canonicalize(mime),
+                handler,
+                self.config.expand_wildcards,
+            )?;
+        }
+        self.save_mime_apps()
+    }
+
+    /// Set a handler as the default for every mime its desktop entry declares,
+    /// optionally only those matching a wildcard, and print a summary of what changed
+    /// Nothing is saved if `dry_run` is true
+    pub fn set_default_app<W: Write>(
+        &mut self,
+        writer: &mut W,
+        handler: &DesktopHandler,
+        filter: Option<&str>,
+        dry_run: bool,
+    ) -> Result<()> {
+        let filter = filter.map(WildMatch::new);
+
+        let mimes = handler
+            .get_entry()?
+            .mime_type
+            .iter()
+            .map(unalias_mime)
+            .filter(|mime| {
+                filter.as_ref().is_none_or(|f| f.matches(mime.as_ref()))
+            })
+            .unique()
+            .collect_vec();
+
+        let mut changes = Vec::new();
+
+        for mime in &mimes {
+            let previous = self
+                .mime_apps
+                .default_apps
+                .get(mime)
+                .and_then(|handlers| handlers.front());
+
+            if previous != Some(handler) {
+                changes.push(DefaultAppChange {
+                    mime: mime.to_string(),
+                    previous: previous
+                        .map(|h| h.to_string())
+                        .unwrap_or_default(),
+                });
+            }
+        }
+
+        if !dry_run {
+            for change in &changes {
+                self.mime_apps.set_handler(
+                    &Mime::from_str(&change.mime)?,
+                    handler,
+                    false,
+                )?;
+            }
+        }
+
+        if !changes.is_empty() {
+            writeln!(
+                writer,
+                "{}",
+                render_table(&changes, self.terminal_output)
+            )?;
+        }
+
+        writeln!(
+            writer,
+            "{} {} as the default for {} mime(s), {} already set",
+            if dry_run { "Would set" } else { "Set" },
             handler,
-            self.config.expand_wildcards,
+            changes.len(),
+            mimes.len() - changes.len(),
         )?;
-        self.mime_apps.save()
+
+        if dry_run || changes.is_empty() {
+            Ok(())
+        } else {
+            self.save_mime_apps()
+        }
     }
 
     /// Open the given paths with their respective handlers
     #[mutants::skip] // Cannot test directly, runs external commands
-    pub fn open_paths(&self, paths: &[UserPath]) -> Result<()> {
+    pub fn open_paths(&mut self, paths: &[UserPath]) -> Result<()> {
         for (handler, paths) in
             self.assign_files_to_handlers(paths)?.into_iter()
         {
+            let (paths, sources): (Vec<_>, Vec<_>) = paths.into_iter().unzip();
             handler.open(self, paths)?;
+
+            // Only desktop handlers can be picked by the selector
+            if let Handler::DesktopHandler(handler) = handler {
+                sources
+                    .iter()
+                    .flatten()
+                    .for_each(|mime| self.usage.record(mime, &handler));
+            }
         }
 
+        self.save_usage();
+
         Ok(())
     }
 
+    /// Save usage history, which only affects ordering and must not fail a command that already ran
+    fn save_usage(&self) {
+        if let Err(e) = self.usage.save() {
+            utils::warn(&format!("could not save usage history: {e}"));
+        }
+    }
+
     /// Helper function to assign files to their respective handlers
+    /// Each file comes with the mime its handler was found through, if any
     fn assign_files_to_handlers(
-        &self,
+        &mut self,
         paths: &[UserPath],
-    ) -> Result<HashMap<Handler, Vec<String>>> {
-        let mut handlers: HashMap<Handler, Vec<String>> = HashMap::new();
+    ) -> Result<HashMap<Handler, AssignedPaths>> {
+        let mut handlers: HashMap<Handler, AssignedPaths> = HashMap::new();
 
         for path in paths.iter() {
+            let (handler, source) = self.get_handler_from_path(path)?;
             handlers
-                .entry(self.get_handler_from_path(path)?)
+                .entry(handler)
                 .or_default()
-                .push(path.to_string())
+                .push((path.to_string(), source))
         }
 
         Ok(handlers)
     }
 
-    /// Get the handler associated with a given path
-    fn get_handler_from_path(&self, path: &UserPath) -> Result<Handler> {
+    /// Get the handler associated with a given path,
+    /// along with the mime it was found through if it is a desktop handler for the path's mime
+    fn get_handler_from_path(
+        &mut self,
+        path: &UserPath,
+    ) -> Result<(Handler, Option<Mime>)> {
         Ok(if let Ok(handler) = self.config.get_regex_handler(path) {
-            handler.into()
+            (handler.into(), None)
         } else {
-            self.get_handler(&path.get_mime()?)?.into()
+            let mime = match path.get_mime() {
+                // Use the fallback handler if the mime could not be figured out
+                // Otherwise, let the user pick the mime if enabled
+                Err(Error::Ambiguous(p)) => {
+                    match self.config.fallback.get(path).cloned() {
+                        Some(fallback) => return Ok((fallback.into(), None)),
+                        None if self.config.open_with_prompt => {
+                            self.prompt_mime()?
+                        }
+                        None => return Err(Error::Ambiguous(p)),
+                    }
+                }
+                mime => mime?,
+            };
+
+            let (handler, source) =
+                self.get_handler_or_fallback(&mime, Some(path))?;
+            (handler.into(), Some(source))
         })
     }
 
@@ -268,158 +971,1808 @@ impl Config {
         Ok(())
     }
 
-    /// Entirely remove a given mime's default application association
-    pub fn unset_handler(&mut self, mime: &Mime) -> Result<()> {
-        if self.mime_apps.unset_handler(mime).is_some() {
-            self.mime_apps.save()?
-        }
-
+    /// Get every mime a given handler is set as the default or an alternative for,
+    /// or that it declares support for, sorted by mime
+    fn handler_mimes(&self, handler: &DesktopHandler) -> Vec<HandlerMimeEntry> {
+        let mut roles = BTreeMap::<String, HandlerRole>::new();
+        let mut assign = |mime: &Mime, role: HandlerRole| {
+            let current = roles.entry(mime.to_string()).or_insert(role);
+            // Keep the most significant role
+            *current = (*current).min(role);
+        };
+
+        for (mime, handlers) in &self.mime_apps.default_apps {
+            match handlers.iter().position(|h| h == handler) {
+                Some(0) => assign(mime, HandlerRole::Default),
+                Some(_) => assign(mime, HandlerRole::Alternative),
+                None => {}
+            }
+        }
+
+        for (mime, handlers) in &self.mime_apps.added_associations {
+            if handlers.contains(handler) {
+                assign(mime, HandlerRole::Alternative)
+            }
+        }
+
+        for (mime, handlers) in &self.system_apps.associations {
+            if handlers.contains(handler) {
+                assign(mime, HandlerRole::Declared)
+            }
+        }
+
+        roles
+            .into_iter()
+            .map(|(mime, role)| HandlerMimeEntry { mime, role })
+            .collect_vec()
+    }
+
+    /// Print every mime a given handler is set as the default or an alternative for,
+    /// or that it declares support for
+    pub fn print_handler_mimes<W: Write>(
+        &self,
+        writer: &mut W,
+        handler: &DesktopHandler,
+        output_json: bool,
+    ) -> Result<()> {
+        let rows = self.handler_mimes(handler);
+
+        let table = if output_json {
+            serde_json::to_string(&rows)?
+        } else {
+            render_table(&rows, self.terminal_output)
+        };
+
+        writeln!(writer, "{table}")?;
+
+        Ok(())
+    }
+
+    /// Print the installed apps,
+    /// optionally only those that match a search term or support a mime, extension or group
+    #[mutants::skip] // Cannot test directly, depends on system state
+    pub fn print_apps<W: Write>(
+        &self,
+        writer: &mut W,
+        search: Option<&str>,
+        mime: Option<&MimeOrExtension>,
+        output_json: bool,
+    ) -> Result<()> {
+        let mimes = mime.map(|m| self.resolve_mimes(m, true)).transpose()?;
+
+        SystemApps::print_entries(
+            writer,
+            SystemApps::get_entries()?,
+            search,
+            mimes.as_deref(),
+            output_json,
+            self.terminal_output,
+        )
+    }
+
+    /// Print how often and how recently handlers have been used to open each mime
+    pub fn print_stats<W: Write>(
+        &self,
+        writer: &mut W,
+        mime: Option<&MimeOrExtension>,
+        output_json: bool,
+    ) -> Result<()> {
+        let mimes = mime.map(|m| self.resolve_mimes(m, true)).transpose()?;
+
+        self.usage.print(
+            writer,
+            mimes.as_deref(),
+            output_json,
+            self.terminal_output,
+        )
+    }
+
+    /// Entirely remove the default application association of each given mime
+    /// Aliases of the mimes are removed too, and mimeapps.list is written once if anything changed
+    pub fn unset_handlers(&mut self, mimes: &[Mime]) -> Result<()> {
+        let mut changed = false;
+
+        for mime in mimes.iter().map(canonicalize) {
+            changed |= self.mime_apps.unset_handler(&mime);
+            for alias in self.mime_apps.aliases_of(&mime) {
+                changed |= self.mime_apps.unset_handler(&alias);
+            }
+        }
+
+        if changed {
+            self.save_mime_apps()?
+        }
+
+        Ok(())
+    }
+
+    /// Remove the default apps and added associations matching a given mime or glob,
+    /// or every one of them if none is given, after backing up mimeapps.list
+    /// Prints the handler each reset mime now resolves to
+    pub fn reset<W: Write>(
+        &mut self,
+        writer: &mut W,
+        pattern: Option<&str>,
+    ) -> Result<()> {
+        self.mime_apps.reset(pattern);
+        let changes = self.mime_apps.changes();
+
+        if changes.is_empty() {
+            return Err(Error::NothingToReset(pattern.map(str::to_owned)));
+        }
+
+        if let Some(backup) = self.mime_apps.backup()? {
+            writeln!(
+                writer,
+                "Backed up mimeapps.list to {}",
+                backup.display()
+            )?;
+        }
+
+        self.save_mime_apps()?;
+
+        // Never prompt with the selector just to show the new handlers
+        self.config.enable_selector = false;
+
+        let rows = changes
+            .iter()
+            .map(|change| ResetRow {
+                section: change.section,
+                mime: change.mime.to_string(),
+                removed: change.before.iter().flat_map(|h| h.iter()).join(", "),
+                now: self
+                    .get_handler(&change.mime)
+                    .map_or("(none)".to_string(), |h| h.to_string()),
+            })
+            .collect_vec();
+
+        writeln!(writer, "{}", render_table(&rows, self.terminal_output))?;
+
+        Ok(())
+    }
+
+    /// Remove a given handler from the default file association of each given mime
+    /// Aliases of the mimes are affected too, and mimeapps.list is written once if anything changed
+    pub fn remove_handlers(
+        &mut self,
+        mimes: &[Mime],
+        handler: &DesktopHandler,
+    ) -> Result<()> {
+        let mut changed = false;
+
+        for mime in mimes.iter().map(canonicalize) {
+            changed |= self.mime_apps.remove_handler(&mime, handler);
+            for alias in self.mime_apps.aliases_of(&mime) {
+                changed |= self.mime_apps.remove_handler(&alias, handler);
+            }
+        }
+
+        if changed {
+            self.save_mime_apps()?
+        }
+
+        Ok(())
+    }
+
+    /// Save mimeapps.list, recording the changes in the journal
+    fn save_mime_apps(&mut self) -> Result<()> {
+        let changes = self.mime_apps.changes();
+        self.mime_apps.save()?;
+
+        if !changes.is_empty() {
+            self.journal.record(changes);
+            // mimeapps.list is already saved, so only the undo history is lost
+            if let Err(e) = self.journal.save() {
+                utils::warn(&format!("could not save journal: {e}"));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Revert the last `n` recorded changes to mimeapps.list
+    pub fn undo<W: Write>(&mut self, writer: &mut W, n: usize) -> Result<()> {
+        for entry in self.journal.undo(n)? {
+            for change in entry.changes.iter().rev() {
+                self.apply_change(change, &change.after, &change.before);
+            }
+            writeln!(writer, "Undid: {}", entry.command)?;
+        }
+
+        self.mime_apps.save()?;
+        self.journal.save()
+    }
+
+    /// Reapply the last `n` undone changes to mimeapps.list
+    pub fn redo<W: Write>(&mut self, writer: &mut W, n: usize) -> Result<()> {
+        for entry in self.journal.redo(n)? {
+            for change in entry.changes.iter() {
+                self.apply_change(change, &change.before, &change.after);
+            }
+            writeln!(writer, "Redid: {}", entry.command)?;
+        }
+
+        self.mime_apps.save()?;
+        self.journal.save()
+    }
+
+    /// Set a mime's handlers from `from` to `to`,
+    /// warning if they have been changed since the change was recorded
+    fn apply_change(
+        &mut self,
+        change: &MimeChange,
+        from: &Option<DesktopList>,
+        to: &Option<DesktopList>,
+    ) {
+        let current = self
+            .mime_apps
+            .section(change.section)
+            .get(&change.mime)
+            .filter(|handlers| !handlers.is_empty());
+
+        if current != from.as_ref() {
+            utils::warn(&format!(
+                "'{}' has been changed since, overwriting it",
+                change.mime
+            ));
+        }
+
+        self.mime_apps
+            .restore(change.section, &change.mime, to.as_ref());
+    }
+
+    /// Export the user's associations and regex handlers in a given format
+    pub fn export<W: Write>(
+        &self,
+        writer: &mut W,
+        format: ExportFormat,
+    ) -> Result<()> {
+        let export = AssociationsExport {
+            added_associations: MimeAppsEntry::from_map(
+                &self.mime_apps.added_associations,
+                "",
+            ),
+            default_apps: MimeAppsEntry::from_map(
+                &self.mime_apps.default_apps,
+                "",
+            ),
+            handlers: self.config.handlers.clone(),
+        };
+
+        let output = match format {
+            ExportFormat::Json => serde_json::to_string_pretty(&export)?,
+            ExportFormat::Toml => toml::to_string(&export)?,
+            ExportFormat::Nix => export.to_nix(),
+        };
+
+        writeln!(writer, "{}", output.trim_end())?;
+
+        Ok(())
+    }
+
+    /// Import associations and regex handlers exported with `export`
+    /// Nothing is changed unless every mime and handler is valid
+    #[mutants::skip] // Cannot test directly, depends on system state
+    pub fn import<W: Write>(
+        &mut self,
+        writer: &mut W,
+        path: &Path,
+        format: Option<ExportFormat>,
+        merge: bool,
+        replace: bool,
+        force: bool,
+    ) -> Result<()> {
+        let format = format.or_else(|| match path.extension()?.to_str()? {
+            "json" => Some(ExportFormat::Json),
+            "toml" => Some(ExportFormat::Toml),
+            _ => None,
+        });
+
+        let contents = std::fs::read_to_string(path)?;
+        let import: AssociationsExport = match format {
+            Some(ExportFormat::Json) => serde_json::from_str(&contents)?,
+            Some(ExportFormat::Toml) => toml::from_str(&contents)?,
+            _ => return Err(Error::UnsupportedImport(path.to_owned())),
+        };
+
+        self.apply_import(writer, import, merge, replace, force)
+    }
+
+    /// Validate and apply imported associations and regex handlers
+    /// Makes testing easier
+    fn apply_import<W: Write>(
+        &mut self,
+        writer: &mut W,
+        import: AssociationsExport,
+        merge: bool,
+        replace: bool,
+        force: bool,
+    ) -> Result<()> {
+        let parse = |entries: &[MimeAppsEntry]| -> Result<Vec<_>> {
+            entries
+                .iter()
+                .map(|entry| {
+                    let (mime, handlers) = entry.parse()?;
+                    self.check_mime(&mime, force)?;
+                    if !force {
+                        handlers
+                            .iter()
+                            .try_for_each(|h| self.check_handler_exists(h))?;
+                    }
+                    Ok((canonicalize(&mime), handlers))
+                })
+                .collect()
+        };
+
+        let added_associations = parse(&import.added_associations)?;
+        let default_apps = parse(&import.default_apps)?;
+        let message = format!(
+            "Imported {} default app(s), {} added association(s) and {} regex handler(s)",
+            default_apps.len(),
+            added_associations.len(),
+            import.handlers.len()
+        );
+
+        if replace {
+            self.mime_apps.added_associations.clear();
+            self.mime_apps.default_apps.clear();
+        }
+
+        import_associations(
+            &mut self.mime_apps.added_associations,
+            added_associations,
+            merge,
+        );
+        import_associations(
+            &mut self.mime_apps.default_apps,
+            default_apps,
+            merge,
+        );
+        self.save_mime_apps()?;
+
+        if !import.handlers.is_empty() {
+            if replace {
+                self.config.handlers = import.handlers;
+            } else {
+                self.config.handlers.merge(import.handlers);
+            }
+            self.config.save_handlers()?;
+        }
+
+        writeln!(writer, "{message}")?;
+
+        Ok(())
+    }
+
+    /// Print how the user's associations differ from another mimeapps.list,
+    /// or from the handlers the system would use if none is given
+    pub fn diff<W: Write>(
+        &self,
+        writer: &mut W,
+        against: Option<&Path>,
+        output_json: bool,
+    ) -> Result<()> {
+        let changes = match against {
+            Some(path) => self.mime_apps.diff(&MimeApps::read_file(path)?),
+            None => self.system_diff(),
+        };
+
+        let rows = changes.iter().map(DiffRow::new).collect_vec();
+
+        let table = if output_json {
+            serde_json::to_string(&rows)?
+        } else {
+            render_colored_table(
+                &rows
+                    .into_iter()
+                    .map(|row| {
+                        let color = row.color();
+                        (row, color)
+                    })
+                    .collect_vec(),
+                self.terminal_output,
+            )
+        };
+
+        writeln!(writer, "{table}")?;
+
+        Ok(())
+    }
+
+    /// Get the changes that would turn the handlers the system would use
+    /// into the ones the user's default apps resolve to
+    /// Only the first handler of each is compared, since it is the one that gets used
+    fn system_diff(&self) -> Vec<MimeChange> {
+        let first_handlers =
+            |handler: &dyn Fn(&Mime) -> Option<DesktopHandler>| {
+                self.mime_apps
+                    .default_apps
+                    .keys()
+                    .filter_map(|mime| {
+                        let mut handlers = DesktopList::default();
+                        handlers.push_back(handler(mime)?);
+                        Some((mime.clone(), handlers))
+                    })
+                    .collect()
+            };
+
+        MimeChange::between(
+            Section::Default,
+            &first_handlers(&|mime| self.system_apps.get_handler(mime)),
+            &first_handlers(&|mime| {
+                self.mime_apps.default_apps.get(mime)?.front().cloned()
+            }),
+        )
+    }
+
+    /// Print the history of changes made to mimeapps.list
+    pub fn print_history<W: Write>(
+        &self,
+        writer: &mut W,
+        output_json: bool,
+    ) -> Result<()> {
+        self.journal
+            .print(writer, output_json, self.terminal_output)
+    }
+
+    /// Rearrange the default handlers of a given mime, extension or group
+    pub fn reorder_handlers(
+        &mut self,
+        mime: &MimeOrExtension,
+        reorder: &Reorder,
+        all_candidates: bool,
+    ) -> Result<()> {
+        let results = self
+            .resolve_mimes(mime, all_candidates)?
+            .iter()
+            .map(|m| self.mime_apps.reorder_handlers(&canonicalize(m), reorder))
+            .collect_vec();
+
+        // Groups and ambiguous extensions only need one of their mimes to have the handlers
+        if results.is_empty() || results.iter().any(|r| r.is_ok()) {
+            self.save_mime_apps()
+        } else {
+            // Prefer telling which handlers are missing over which mimes have none
+            let mut errors =
+                results.into_iter().filter_map(|r| r.err()).collect_vec();
+            let index = errors
+                .iter()
+                .position(|e| matches!(e, Error::NotAssociated(..)))
+                .unwrap_or_default();
+            Err(errors.swap_remove(index))
+        }
+    }
+
+    /// Rewrite aliased mimes in mimeapps.list to their canonical names
+    pub fn normalize_aliases<W: Write>(
+        &mut self,
+        writer: &mut W,
+    ) -> Result<()> {
+        let renamed = self.mime_apps.normalize_aliases();
+
+        for (alias, canonical) in renamed.iter() {
+            writeln!(writer, "{alias} -> {canonical}")?;
+        }
+
+        if !renamed.is_empty() {
+            self.save_mime_apps()?
+        }
+
+        Ok(())
+    }
+
+    /// Override the set selector
+    /// Currently assumes the config file will never be saved to other than to create an existing one
+    pub fn override_selector(&mut self, selector_args: SelectorArgs) {
+        self.config.override_selector(selector_args);
+    }
+}
+
+/// Paths assigned to a handler, each with the mime the handler was found through, if any
+type AssignedPaths = Vec<(String, Option<Mime>)>;
+
+/// Add imported associations to existing ones
+/// When merging, new handlers are appended to those of mimes that already have some,
+/// otherwise they replace them
+fn import_associations(
+    existing: &mut BTreeMap<Mime, DesktopList>,
+    imported: Vec<(Mime, DesktopList)>,
+    merge: bool,
+) {
+    for (mime, handlers) in imported {
+        if merge {
+            let list = existing.entry(mime).or_default();
+            for handler in handlers.iter() {
+                if !list.contains(handler) {
+                    list.push_back(handler.clone());
+                }
+            }
+        } else {
+            existing.insert(mime, handlers);
+        }
+    }
+}
+
+/// Get the canonical name of a given mime, warning the user if it is an alias
+fn canonicalize(mime: &Mime) -> Mime {
+    let canonical = unalias_mime(mime);
+
+    if canonical != *mime {
+        utils::warn(&format!(
+            "'{mime}' is an alias of '{canonical}', using '{canonical}' instead"
+        ));
+    }
+
+    canonical
+}
+
+/// Internal helper struct for turning MimeApps into tabular data
+#[derive(PartialEq, Eq, PartialOrd, Ord, Tabled, Serialize, Deserialize)]
+struct MimeAppsEntry {
+    mime: String,
+    #[tabled(display_with("Self::display_handlers", self))]
+    handlers: Vec<String>,
+    #[tabled(skip)]
+    #[serde(skip)]
+    // This field should not appear in any output
+    // It is only used for determining how to render output
+    separator: String,
+}
+
+impl MimeAppsEntry {
+    /// Create a new `MimeAppsEntry`
+    fn new(
+        mime: &Mime,
+        handlers: &VecDeque<DesktopHandler>,
+        separator: &str,
+    ) -> Self {
+        Self {
+            mime: mime.to_string(),
+            handlers: handlers
+                .iter()
+                .map(|x| x.to_string())
+                .collect::<Vec<String>>(),
+            separator: separator.to_string(),
+        }
+    }
+
+    /// Create a sorted list of `MimeAppsEntry`s from a map of associations
+    fn from_map(
+        map: &BTreeMap<Mime, DesktopList>,
+        separator: &str,
+    ) -> Vec<Self> {
+        let mut rows = map
+            .iter()
+            .map(|(mime, handlers)| Self::new(mime, handlers, separator))
+            .collect::<Vec<_>>();
+        rows.sort_unstable();
+        rows
+    }
+
+    /// Parse the mime and handlers of the entry
+    fn parse(&self) -> Result<(Mime, DesktopList)> {
+        Ok((
+            Mime::from_str(&self.mime)?,
+            DesktopList::from_str(&self.handlers.join(";"))?,
+        ))
+    }
+
+    /// Display list of handlers as a string
+    fn display_handlers(&self) -> String {
+        self.handlers.join(&self.separator)
+    }
+}
+
+/// Where a candidate handler comes from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum CandidateSource {
+    /// Regex handler from the config file
+    Regex,
+    /// Default application in mimeapps.list
+    Default,
+    /// Default application for a wildcard in mimeapps.list
+    Wildcard,
+    /// Added association in mimeapps.list
+    Added,
+    /// Desktop entry declaring support for the mime
+    System,
+    /// Fallback handler from the config file
+    Fallback,
+}
+
+impl std::fmt::Display for CandidateSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Regex => "regex",
+            Self::Default => "user default",
+            Self::Wildcard => "wildcard",
+            Self::Added => "added association",
+            Self::System => "system",
+            Self::Fallback => "fallback",
+        })
+    }
+}
+
+/// Internal helper struct for turning candidate handlers into tabular data
+#[derive(Tabled, Serialize)]
+struct CandidateEntry {
+    /// Mime or wildcard the handler was found under, if any
+    mime: String,
+    source: CandidateSource,
+    handler: String,
+    name: String,
+    exists: bool,
+    command: String,
+}
+
+impl CandidateEntry {
+    /// Create a new `CandidateEntry`, looking up the handler's desktop entry
+    fn new(
+        config: &Config,
+        mime: &str,
+        source: CandidateSource,
+        handler: &Handler,
+    ) -> Self {
+        let entry = handler.get_entry().ok();
+
+        let (handler_name, exists) = match handler {
+            Handler::DesktopHandler(h) => (
+                h.to_string(),
+                h.path().is_ok_and(|p| p.exists()) && entry.is_some(),
+            ),
+            Handler::RegexHandler(_) => (
+                entry.as_ref().map(|e| e.exec.clone()).unwrap_or_default(),
+                true,
+            ),
+        };
+
+        Self {
+            mime: mime.to_string(),
+            source,
+            handler: handler_name,
+            name: entry.as_ref().map(|e| e.name.clone()).unwrap_or_default(),
+            exists,
+            command: entry
+                .and_then(|e| e.get_cmd(config, vec![]).ok())
+                .map(|(cmd, args)| std::iter::once(cmd).chain(args).join(" "))
+                .unwrap_or_default(),
+        }
+    }
+}
+
+/// Internal helper struct for turning the handlers of a group into tabular data
+#[derive(Tabled, Serialize)]
+struct GroupHandlerEntry {
+    mime: String,
+    handler: String,
+}
+
+/// Internal helper struct for summarizing changes made by `set_default_app`
+#[derive(Tabled)]
+struct DefaultAppChange {
+    mime: String,
+    previous: String,
+}
+
+/// How a handler relates to a mime
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum HandlerRole {
+    /// The handler is the user's default for the mime
+    Default,
+    /// The handler is set or added for the mime, but is not the default
+    Alternative,
+    /// The handler only declares support for the mime in its desktop entry
+    Declared,
+}
+
+impl std::fmt::Display for HandlerRole {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Default => "default",
+            Self::Alternative => "alternative",
+            Self::Declared => "declared",
+        })
+    }
+}
+
+/// Internal helper struct for turning a handler's mimes into tabular data
+#[derive(Tabled, Serialize)]
+struct HandlerMimeEntry {
+    mime: String,
+    role: HandlerRole,
+}
+
+/// Internal helper struct for turning MimeApps into tabular data
+#[derive(Serialize)]
+struct MimeAppsTable {
+    added_associations: Vec<MimeAppsEntry>,
+    default_apps: Vec<MimeAppsEntry>,
+    system_apps: Vec<MimeAppsEntry>,
+}
+
+impl MimeAppsTable {
+    /// Create a new `MimeAppsTable`
+    fn new(
+        mimeapps: &MimeApps,
+        system_apps: &SystemApps,
+        terminal_output: bool,
+    ) -> Self {
+        // If output is a terminal, optimize for readability
+        // Otherwise, if piped, optimize for parseability
+        let separator = if terminal_output { ",\n" } else { ", " };
+
+        let to_entries = |map| MimeAppsEntry::from_map(map, separator);
+        Self {
+            added_associations: to_entries(&mimeapps.added_associations),
+            default_apps: to_entries(&mimeapps.default_apps),
+            system_apps: to_entries(&system_apps.associations),
+        }
+    }
+}
+
+/// Internal helper struct for turning reset mimes into tabular data
+#[derive(Tabled)]
+struct ResetRow {
+    section: Section,
+    mime: String,
+    /// Handlers that were removed
+    removed: String,
+    /// Handler the mime now resolves to
+    now: String,
+}
+
+/// Internal helper struct for turning changes between associations into tabular data
+#[derive(Tabled, Serialize)]
+struct DiffRow {
+    section: Section,
+    mime: String,
+    change: ChangeKind,
+    #[tabled(display_with = "Self::display_handlers")]
+    before: Vec<String>,
+    #[tabled(display_with = "Self::display_handlers")]
+    after: Vec<String>,
+}
+
+impl DiffRow {
+    fn new(change: &MimeChange) -> Self {
+        let handlers = |list: &Option<DesktopList>| {
+            list.iter()
+                .flat_map(|l| l.iter())
+                .map(|h| h.to_string())
+                .collect_vec()
+        };
+
+        Self {
+            section: change.section,
+            mime: change.mime.to_string(),
+            change: change.kind(),
+            before: handlers(&change.before),
+            after: handlers(&change.after),
+        }
+    }
+
+    /// Get the color to highlight the row with
+    fn color(&self) -> Color {
+        match self.change {
+            ChangeKind::Added => Color::FG_GREEN,
+            ChangeKind::Removed => Color::FG_RED,
+            ChangeKind::Changed => Color::FG_YELLOW,
+        }
+    }
+
+    /// Display list of handlers as a string
+    fn display_handlers(handlers: &[String]) -> String {
+        if handlers.is_empty() {
+            "(none)".to_string()
+        } else {
+            handlers.join(", ")
+        }
+    }
+}
+
+/// Portable representation of the user's associations and regex handlers,
+/// used by `handlr export` and `handlr import`
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+struct AssociationsExport {
+    added_associations: Vec<MimeAppsEntry>,
+    default_apps: Vec<MimeAppsEntry>,
+    // NOTE: Must come last, as it is serialized as an array of tables
+    #[serde(skip_serializing_if = "RegexApps::is_empty")]
+    handlers: RegexApps,
+}
+
+impl AssociationsExport {
+    /// Render as an attribute set for home-manager's `xdg.mimeApps` module
+    fn to_nix(&self) -> String {
+        // Escape a string for use in a nix string literal
+        let string = |s: &str| {
+            format!(
+                "\"{}\"",
+                s.replace('\\', "\\\\")
+                    .replace('"', "\\\"")
+                    .replace("${", "\\${")
+            )
+        };
+
+        let attrs = |entries: &[MimeAppsEntry]| {
+            entries
+                .iter()
+                .map(|entry| {
+                    format!(
+                        "      {} = [ {} ];\n",
+                        string(&entry.mime),
+                        entry.handlers.iter().map(|h| string(h)).join(" ")
+                    )
+                })
+                .collect::<String>()
+        };
+
+        let mut nix = String::from("{\n");
+
+        if !self.handlers.is_empty() {
+            nix.push_str("  # Regex handlers are not supported by xdg.mimeApps and were left out\n");
+        }
+
+        nix.push_str("  xdg.mimeApps = {\n    enable = true;\n");
+        nix.push_str("    defaultApplications = {\n");
+        nix.push_str(&attrs(&self.default_apps));
+        nix.push_str("    };\n");
+        nix.push_str("    associations.added = {\n");
+        nix.push_str(&attrs(&self.added_associations));
+        nix.push_str("    };\n  };\n}");
+
+        nix
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use std::path::PathBuf;
+
+    #[test]
+    fn wildcard_mimes() -> Result<()> {
+        let mut config = Config::default();
+        config.add_handlers(
+            &[Mime::from_str("video/*")?],
+            &DesktopHandler::from_str("tests/mpv.desktop")?,
+        )?;
+        config.add_handlers(
+            &[Mime::from_str("video/webm")?],
+            &DesktopHandler::from_str("tests/brave.desktop")?,
+        )?;
+
+        assert_eq!(
+            config
+                .get_handler(&Mime::from_str("video/mp4")?)?
+                .to_string(),
+            "tests/mpv.desktop"
+        );
+        assert_eq!(
+            config
+                .get_handler(&Mime::from_str("video/asdf")?)?
+                .to_string(),
+            "tests/mpv.desktop"
+        );
+        assert_eq!(
+            config
+                .get_handler(&Mime::from_str("video/webm")?)?
+                .to_string(),
+            "tests/brave.desktop"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn parent_mimes() -> Result<()> {
+        let mut config = Config::default();
+        config.add_handlers(
+            &[mime::TEXT_PLAIN],
+            &DesktopHandler::from_str("tests/Helix.desktop")?,
+        )?;
+
+        // Subclasses of text/plain should fall back to it
+        for mime in
+            ["text/markdown", "text/x-rust", "application/x-shellscript"]
+        {
+            assert_eq!(
+                config.get_handler(&Mime::from_str(mime)?)?.to_string(),
+                "tests/Helix.desktop"
+            );
+        }
+
+        // More specific mimes should still take precedence
+        config
+            .system_apps
+            .associations
+            .entry(Mime::from_str("text/markdown")?)
+            .or_default()
+            .push_back(DesktopHandler::from_str("tests/apostrophe.desktop")?);
+        assert_eq!(
+            config
+                .get_handler(&Mime::from_str("text/markdown")?)?
+                .to_string(),
+            "tests/apostrophe.desktop"
+        );
+
+        // Unrelated mimes should not fall back to text/plain
+        assert!(config.get_handler(&mime::IMAGE_PNG).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn alias_mimes() -> Result<()> {
+        let mut config = Config::default();
+        config.add_handlers(
+            &[mime::APPLICATION_PDF],
+            &DesktopHandler::from_str("tests/mupdf.desktop")?,
+        )?;
+
+        assert_eq!(
+            config
+                .get_handler(&Mime::from_str("application/x-pdf")?)?
+                .to_string(),
+            "tests/mupdf.desktop"
+        );
+
+        // Aliases already present in mimeapps.list should still be checked first
+        config
+            .mime_apps
+            .default_apps
+            .entry(Mime::from_str("application/x-pdf")?)
+            .or_default()
+            .push_back(DesktopHandler::from_str("tests/zathura.desktop")?);
+
+        assert_eq!(
+            config
+                .get_handler(&Mime::from_str("application/x-pdf")?)?
+                .to_string(),
+            "tests/zathura.desktop"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn canonicalize_aliases() -> Result<()> {
+        let mut config = Config::default();
+
+        config.set_handlers(
+            &[Mime::from_str("application/x-pdf")?],
+            &DesktopHandler::from_str("tests/mupdf.desktop")?,
+        )?;
+
+        assert!(config
+            .mime_apps
+            .default_apps
+            .contains_key(&mime::APPLICATION_PDF));
+        assert!(config
+            .mime_apps
+            .aliases_of(&mime::APPLICATION_PDF)
+            .is_empty());
+
+        // Aliases already present in mimeapps.list should be read and unset
+        config
+            .mime_apps
+            .default_apps
+            .entry(Mime::from_str("application/x-pdf")?)
+            .or_default()
+            .push_back(DesktopHandler::from_str("tests/zathura.desktop")?);
+        config.remove_handlers(
+            &[mime::APPLICATION_PDF],
+            &DesktopHandler::from_str("tests/mupdf.desktop")?,
+        )?;
+
+        assert_eq!(
+            config.get_handler(&mime::APPLICATION_PDF)?.to_string(),
+            "tests/zathura.desktop"
+        );
+
+        config.unset_handlers(&[Mime::from_str("application/x-pdf")?])?;
+
+        assert!(config.get_handler(&mime::APPLICATION_PDF).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn check_mimes() -> Result<()> {
+        let config = Config::default();
+
+        config.check_mime(&mime::TEXT_PLAIN, false)?;
+        config.check_mime(&Mime::from_str("video/*")?, false)?;
+        config.check_mime(&Mime::from_str("text/plian")?, true)?;
+
+        let error = config
+            .check_mime(&Mime::from_str("text/plian")?, false)
+            .expect_err("Mistyped mime should be rejected");
+        assert_eq!(
+            error.to_string(),
+            "unknown mime 'text/plian' (did you mean 'text/plain'?), use --force to use it anyway"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn resolve_app_names() -> Result<()> {
+        let config = Config::default();
+
+        let entries = || -> Result<Vec<(OsString, DesktopEntry)>> {
+            Ok(vec![
+                (
+                    "Helix.desktop".into(),
+                    DesktopEntry::try_from(PathBuf::from(
+                        "tests/Helix.desktop",
+                    ))?,
+                ),
+                (
+                    "org.wezfurlong.wezterm.desktop".into(),
+                    DesktopEntry::try_from(PathBuf::from(
+                        "tests/org.wezfurlong.wezterm.desktop",
+                    ))?,
+                ),
+                (
+                    "nvim.desktop".into(),
+                    DesktopEntry {
+                        name: "Neovim".into(),
+                        generic_name: Some("Text Editor".into()),
+                        exec: "nvim %F".into(),
+                        ..Default::default()
+                    },
+                ),
+            ])
+        };
+
+        let resolve = |config: &Config, name: &str| -> Result<String> {
+            Ok(config
+                .resolve_handler_from(
+                    &DesktopHandler::from_str(name)?,
+                    entries()?,
+                )?
+                .to_string())
+        };
+
+        assert_eq!(
+            resolve(&config, "wezterm")?,
+            "org.wezfurlong.wezterm.desktop"
+        );
+        assert_eq!(resolve(&config, "neovim")?, "nvim.desktop");
+        assert_eq!(resolve(&config, "hx")?, "Helix.desktop");
+        assert_eq!(resolve(&config, "helix")?, "Helix.desktop");
+        // Unknown names are left for `check_handler` to deal with
+        assert_eq!(resolve(&config, "kakoune")?, "kakoune");
+
+        assert_eq!(
+            resolve(&config, "text editor")
+                .expect_err("Ambiguous name should be rejected")
+                .to_string(),
+            "'text editor' could refer to any of Helix (Helix.desktop), Neovim (nvim.desktop), use one of their desktop IDs or enable the selector"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn reverse_lookup() -> Result<()> {
+        let mut config = Config::default();
+        let mpv = DesktopHandler::assume_valid("mpv.desktop".into());
+        let vlc = DesktopHandler::assume_valid("vlc.desktop".into());
+
+        config.add_handlers(&[Mime::from_str("video/mp4")?], &mpv)?;
+        config.add_handlers(&[Mime::from_str("video/webm")?], &vlc)?;
+        config.add_handlers(&[Mime::from_str("video/webm")?], &mpv)?;
+        config.add_handlers(&[Mime::from_str("audio/*")?], &vlc)?;
+        config
+            .mime_apps
+            .added_associations
+            .entry(Mime::from_str("video/ogg")?)
+            .or_default()
+            .push_back(mpv.clone());
+        for mime in ["video/mp4", "video/x-matroska"] {
+            config
+                .system_apps
+                .associations
+                .entry(Mime::from_str(mime)?)
+                .or_default()
+                .push_back(mpv.clone());
+        }
+
+        let mut buffer = Vec::new();
+        config.print_handler_mimes(&mut buffer, &mpv, false)?;
+        goldie::assert!(String::from_utf8(buffer)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn reverse_lookup_json() -> Result<()> {
+        let mut config = Config::default();
+        let mpv = DesktopHandler::assume_valid("mpv.desktop".into());
+
+        config.add_handlers(&[Mime::from_str("video/mp4")?], &mpv)?;
+        config
+            .system_apps
+            .associations
+            .entry(Mime::from_str("video/x-matroska")?)
+            .or_default()
+            .push_back(mpv.clone());
+
+        let mut buffer = Vec::new();
+        config.print_handler_mimes(&mut buffer, &mpv, true)?;
+        goldie::assert!(String::from_utf8(buffer)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn set_default_app() -> Result<()> {
+        let mut config = Config::default();
+        let helix = DesktopHandler::from_str("tests/Helix.desktop")?;
+
+        config.set_handlers(&[mime::TEXT_PLAIN], &helix)?;
+        config.set_handlers(
+            &[Mime::from_str("text/x-csrc")?],
+            &DesktopHandler::from_str("tests/nvim.desktop")?,
+        )?;
+
+        let mut buffer = Vec::new();
+        config.set_default_app(&mut buffer, &helix, Some("text/x-c*"), true)?;
+        goldie::assert!(String::from_utf8(buffer)?);
+
+        // Nothing changes on a dry run
+        assert_eq!(
+            config
+                .get_handler(&Mime::from_str("text/x-csrc")?)?
+                .to_string(),
+            "tests/nvim.desktop"
+        );
+        assert!(!config
+            .mime_apps
+            .default_apps
+            .contains_key(&Mime::from_str("text/x-c++hdr")?));
+
+        config.set_default_app(&mut Vec::new(), &helix, None, false)?;
+
+        for mime in
+            ["text/x-csrc", "text/x-c++hdr", "application/x-shellscript"]
+        {
+            assert_eq!(
+                config.get_handler(&Mime::from_str(mime)?)?.to_string(),
+                "tests/Helix.desktop"
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn mime_groups() -> Result<()> {
+        let mut config = Config::default();
+        config.config.groups.insert(
+            "docs".into(),
+            vec![
+                mime::APPLICATION_PDF,
+                Mime::from_str("application/epub+zip")?,
+            ],
+        );
+        config
+            .config
+            .groups
+            .insert("browser".into(), vec![Mime::from_str("text/html")?]);
+        config.config.groups.insert("empty".into(), Vec::new());
+
+        let resolve = |mime: &str| -> Result<Vec<String>> {
+            Ok(config
+                .resolve_mimes(&MimeOrExtension::from_str(mime)?, false)?
+                .iter()
+                .map(|m| m.to_string())
+                .collect_vec())
+        };
+
+        assert_eq!(resolve("text/plain")?, ["text/plain"]);
+        assert_eq!(
+            resolve("@docs")?,
+            ["application/pdf", "application/epub+zip"]
+        );
+        // User groups take precedence over built-in ones
+        assert_eq!(resolve("@browser")?, ["text/html"]);
+        assert_eq!(resolve("@terminal")?, ["x-scheme-handler/terminal"]);
+        assert_eq!(
+            resolve("@image-veiwer")
+                .expect_err("Unknown group should be rejected")
+                .to_string(),
+            "unknown mime group '@image-veiwer' (did you mean 'image-viewer'?)"
+        );
+
+        assert_eq!(
+            resolve("@empty")
+                .expect_err("Empty group should be rejected")
+                .to_string(),
+            "mime group '@empty' has no mimes"
+        );
+
+        // Every built-in group should only contain known mimes
+        for group in Config::default().config.group_names() {
+            for mime in resolve(&format!("@{group}"))? {
+                config.check_mime(&Mime::from_str(&mime)?, false)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn handler_candidates_with_sources() -> Result<()> {
+        let mut config = Config {
+            terminal_output: true,
+            ..Default::default()
+        };
+        let helix = DesktopHandler::from_str("tests/Helix.desktop")?;
+
+        config.add_handlers(
+            &[Mime::from_str("text/markdown")?],
+            &DesktopHandler::assume_valid("apostrophe.desktop".into()),
+        )?;
+        config.add_handlers(&[Mime::from_str("text/*")?], &helix)?;
+        config
+            .mime_apps
+            .added_associations
+            .entry(Mime::from_str("text/markdown")?)
+            .or_default()
+            .push_back(DesktopHandler::assume_valid(
+                "ghostwriter.desktop".into(),
+            ));
+        config
+            .system_apps
+            .associations
+            .entry(mime::TEXT_PLAIN)
+            .or_default()
+            .push_back(helix.clone());
+
+        let mut buffer = Vec::new();
+        config.show_handler_candidates(
+            &mut buffer,
+            Some(&MimeOrExtension::from_str("text/markdown")?),
+            None,
+            false,
+        )?;
+        goldie::assert!(String::from_utf8(buffer)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn handler_candidates_with_sources_json() -> Result<()> {
+        let mut config = Config::default();
+        config.add_handlers(
+            &[Mime::from_str("text/*")?],
+            &DesktopHandler::from_str("tests/Helix.desktop")?,
+        )?;
+        // Helix runs in a terminal, so one is needed to build its command
+        config.add_handlers(
+            &[Mime::from_str("x-scheme-handler/terminal")?],
+            &DesktopHandler::from_str("tests/org.wezfurlong.wezterm.desktop")?,
+        )?;
+        config.config.fallback.file =
+            Some(DesktopHandler::assume_valid("hexyl.desktop".into()));
+
+        let mut buffer = Vec::new();
+        config.show_handler_candidates(
+            &mut buffer,
+            None,
+            Some(&UserPath::from_str("tests/rust.vim")?),
+            true,
+        )?;
+        goldie::assert!(String::from_utf8(buffer)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn reorder_group_handlers() -> Result<()> {
+        let mut config = Config::default();
+        let [firefox, chromium] = ["firefox.desktop", "chromium.desktop"]
+            .map(|h| DesktopHandler::assume_valid(h.into()));
+
+        config.add_handlers(&[Mime::from_str("text/html")?], &chromium)?;
+        config.add_handlers(&[Mime::from_str("text/html")?], &firefox)?;
+        config.add_handlers(
+            &[Mime::from_str("x-scheme-handler/https")?],
+            &chromium,
+        )?;
+
+        // Not every mime in the group needs to have the handler
+        config.reorder_handlers(
+            &MimeOrExtension::from_str("@browser")?,
+            &Reorder::Promote(firefox.clone()),
+            false,
+        )?;
+        assert_eq!(
+            config.mime_apps.default_apps[&mime::TEXT_HTML]
+                .front()
+                .map(|h| h.to_string()),
+            Some("firefox.desktop".to_string())
+        );
+
+        assert!(matches!(
+            config.reorder_handlers(
+                &MimeOrExtension::from_str("@browser")?,
+                &Reorder::Promote(DesktopHandler::assume_valid(
+                    "mpv.desktop".into()
+                )),
+                false,
+            ),
+            Err(Error::NotAssociated(..))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn undo_redo_changes() -> Result<()> {
+        let mut config = Config::default();
+        let nvim = DesktopHandler::assume_valid("nvim.desktop".into());
+        let helix = DesktopHandler::assume_valid("Helix.desktop".into());
+        let handlers = |config: &Config| {
+            config
+                .mime_apps
+                .default_apps
+                .get(&mime::TEXT_PLAIN)
+                .map(|h| h.to_string())
+        };
+
+        config.set_handlers(&[mime::TEXT_PLAIN], &nvim)?;
+        config.add_handlers(&[mime::TEXT_PLAIN], &helix)?;
+        config.unset_handlers(&[mime::TEXT_PLAIN])?;
+        assert_eq!(handlers(&config), None);
+
+        let mut buffer = Vec::new();
+        config.undo(&mut buffer, 2)?;
+        assert_eq!(handlers(&config), Some("nvim.desktop;".to_string()));
+
+        config.redo(&mut buffer, 1)?;
+        assert_eq!(
+            handlers(&config),
+            Some("nvim.desktop;Helix.desktop;".to_string())
+        );
+
+        config.undo(&mut buffer, 5)?;
+        assert_eq!(handlers(&config), None);
+        assert!(matches!(
+            config.undo(&mut buffer, 1),
+            Err(Error::NoHistory(_))
+        ));
+
+        // Each undone and redone command is reported
+        assert_eq!(String::from_utf8(buffer)?.lines().count(), 5);
+
+        Ok(())
+    }
+
+    // Helper function to create a config with arbitrary user and system associations to compare
+    fn diff_test_config() -> Result<Config> {
+        let mut config = Config::default();
+
+        config.set_handlers(
+            &[mime::TEXT_PLAIN],
+            &DesktopHandler::assume_valid("Helix.desktop".into()),
+        )?;
+        config.add_handlers(
+            &[mime::TEXT_PLAIN],
+            &DesktopHandler::assume_valid("nvim.desktop".into()),
+        )?;
+        config.set_handlers(
+            &[Mime::from_str("x-scheme-handler/https")?],
+            &DesktopHandler::assume_valid("firefox.desktop".into()),
+        )?;
+        config.set_handlers(
+            &[Mime::from_str("video/*")?],
+            &DesktopHandler::assume_valid("mpv.desktop".into()),
+        )?;
+        config
+            .mime_apps
+            .added_associations
+            .entry(Mime::from_str("x-scheme-handler/terminal")?)
+            .or_default()
+            .push_back(DesktopHandler::assume_valid(
+                "org.wezfurlong.wezterm.desktop".into(),
+            ));
+
+        for (mime, handler) in [
+            ("text/plain", "nvim.desktop"),
+            ("x-scheme-handler/https", "firefox.desktop"),
+        ] {
+            config
+                .system_apps
+                .associations
+                .entry(Mime::from_str(mime)?)
+                .or_default()
+                .push_back(DesktopHandler::assume_valid(handler.into()));
+        }
+
+        config.terminal_output = false;
+
+        Ok(config)
+    }
+
+    #[test]
+    fn reset_to_system_handlers() -> Result<()> {
+        let mut config = diff_test_config()?;
+        config.mime_apps.save()?;
+        let mut buffer = Vec::new();
+
+        config.reset(&mut buffer, Some("text/*"))?;
+        config.reset(&mut buffer, None)?;
+        goldie::assert!(String::from_utf8(buffer)?);
+
+        assert!(config.mime_apps.default_apps.is_empty());
+        assert!(config.mime_apps.added_associations.is_empty());
+        assert_eq!(
+            config
+                .reset(&mut Vec::new(), Some("image/*"))
+                .expect_err("Nothing should be left to reset")
+                .to_string(),
+            "nothing to reset, no associations match 'image/*'"
+        );
+        assert!(matches!(
+            config.reset(&mut Vec::new(), None),
+            Err(Error::NothingToReset(None))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn diff_against_system() -> Result<()> {
+        let mut buffer = Vec::new();
+        diff_test_config()?.diff(&mut buffer, None, false)?;
+        goldie::assert!(String::from_utf8(buffer)?);
+        Ok(())
+    }
+
+    #[test]
+    fn diff_against_file() -> Result<()> {
+        let mut buffer = Vec::new();
+        diff_test_config()?.diff(
+            &mut buffer,
+            Some(Path::new("tests/mimeapps_comments.list")),
+            false,
+        )?;
+        goldie::assert!(String::from_utf8(buffer)?);
+        Ok(())
+    }
+
+    #[test]
+    fn diff_against_file_json() -> Result<()> {
+        let mut buffer = Vec::new();
+        diff_test_config()?.diff(
+            &mut buffer,
+            Some(Path::new("tests/mimeapps_comments.list")),
+            true,
+        )?;
+        goldie::assert!(String::from_utf8(buffer)?);
         Ok(())
     }
 
-    /// Remove a given handler from a given mime's default file associaion
-    pub fn remove_handler(
-        &mut self,
-        mime: &Mime,
-        handler: &DesktopHandler,
-    ) -> Result<()> {
-        if self.mime_apps.remove_handler(mime, handler).is_some() {
-            self.mime_apps.save()?
-        }
+    // Helper function to create a config with arbitrary associations to export
+    fn export_test_config() -> Result<Config> {
+        let mut config = Config::default();
 
-        Ok(())
+        config.add_handlers(
+            &[mime::TEXT_PLAIN],
+            &DesktopHandler::assume_valid("Helix.desktop".into()),
+        )?;
+        config.add_handlers(
+            &[mime::TEXT_PLAIN],
+            &DesktopHandler::assume_valid("nvim.desktop".into()),
+        )?;
+        config.set_handlers(
+            &[Mime::from_str("x-scheme-handler/https")?],
+            &DesktopHandler::assume_valid("firefox.desktop".into()),
+        )?;
+        config
+            .mime_apps
+            .added_associations
+            .entry(Mime::from_str("image/png")?)
+            .or_default()
+            .push_back(DesktopHandler::assume_valid("gimp.desktop".into()));
+
+        config.config.handlers = serde_json::from_str(
+            r#"[{ "exec": "freetube %u", "regexes": ["(https://)?(www\\.)?youtu(be\\.com|\\.be)/.*"] }]"#,
+        )?;
+
+        Ok(config)
     }
 
-    /// Override the set selector
-    /// Currently assumes the config file will never be saved to other than to create an existing one
-    pub fn override_selector(&mut self, selector_args: SelectorArgs) {
-        self.config.override_selector(selector_args);
+    // Helper function to test exporting in a given format
+    fn test_export(format: ExportFormat) -> Result<String> {
+        let mut buffer = Vec::new();
+        export_test_config()?.export(&mut buffer, format)?;
+        Ok(String::from_utf8(buffer)?)
     }
-}
 
-/// Internal helper struct for turning MimeApps into tabular data
-#[derive(PartialEq, Eq, PartialOrd, Ord, Tabled, Serialize)]
-struct MimeAppsEntry {
-    mime: String,
-    #[tabled(display_with("Self::display_handlers", self))]
-    handlers: Vec<String>,
-    #[tabled(skip)]
-    #[serde(skip_serializing)]
-    // This field should not appear in any output
-    // It is only used for determining how to render output
-    separator: String,
-}
+    #[test]
+    fn export_json() -> Result<()> {
+        goldie::assert!(test_export(ExportFormat::Json)?);
+        Ok(())
+    }
 
-impl MimeAppsEntry {
-    /// Create a new `MimeAppsEntry`
-    fn new(
-        mime: &Mime,
-        handlers: &VecDeque<DesktopHandler>,
-        separator: &str,
-    ) -> Self {
-        Self {
-            mime: mime.to_string(),
-            handlers: handlers
-                .iter()
-                .map(|x| x.to_string())
-                .collect::<Vec<String>>(),
-            separator: separator.to_string(),
-        }
+    #[test]
+    fn export_toml() -> Result<()> {
+        goldie::assert!(test_export(ExportFormat::Toml)?);
+        Ok(())
     }
 
-    /// Display list of handlers as a string
-    fn display_handlers(&self) -> String {
-        self.handlers.join(&self.separator)
+    #[test]
+    fn export_nix() -> Result<()> {
+        goldie::assert!(test_export(ExportFormat::Nix)?);
+        Ok(())
     }
-}
 
-/// Internal helper struct for turning MimeApps into tabular data
-#[derive(Serialize)]
-struct MimeAppsTable {
-    added_associations: Vec<MimeAppsEntry>,
-    default_apps: Vec<MimeAppsEntry>,
-    system_apps: Vec<MimeAppsEntry>,
-}
+    #[test]
+    fn import_associations() -> Result<()> {
+        let exported = export_test_config()?;
+        let export = |format| -> Result<AssociationsExport> {
+            let mut buffer = Vec::new();
+            exported.export(&mut buffer, format)?;
+            let buffer = String::from_utf8(buffer)?;
+            Ok(match format {
+                ExportFormat::Toml => toml::from_str(&buffer)?,
+                _ => serde_json::from_str(&buffer)?,
+            })
+        };
 
-impl MimeAppsTable {
-    /// Create a new `MimeAppsTable`
-    fn new(
-        mimeapps: &MimeApps,
-        system_apps: &SystemApps,
-        terminal_output: bool,
-    ) -> Self {
-        // If output is a terminal, optimize for readability
-        // Otherwise, if piped, optimize for parseability
-        let separator = if terminal_output { ",\n" } else { ", " };
+        let mut config = Config::default();
+        config.set_handlers(
+            &[mime::TEXT_HTML],
+            &DesktopHandler::assume_valid("firefox.desktop".into()),
+        )?;
 
-        let to_entries =
-            |map: &BTreeMap<Mime, DesktopList>| -> Vec<MimeAppsEntry> {
-                let mut rows = map
-                    .iter()
-                    .map(|(mime, handlers)| {
-                        MimeAppsEntry::new(mime, handlers, separator)
-                    })
-                    .collect::<Vec<_>>();
-                rows.sort_unstable();
-                rows
-            };
-        Self {
-            added_associations: to_entries(&mimeapps.added_associations),
-            default_apps: to_entries(&mimeapps.default_apps),
-            system_apps: to_entries(&system_apps.associations),
-        }
-    }
-}
+        let mut buffer = Vec::new();
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use pretty_assertions::assert_eq;
+        // Importing keeps associations that are not part of the import
+        config.apply_import(
+            &mut buffer,
+            export(ExportFormat::Json)?,
+            false,
+            false,
+            true,
+        )?;
+        assert_eq!(config.mime_apps.default_apps.len(), 3);
+        assert_eq!(
+            config.mime_apps.added_associations,
+            exported.mime_apps.added_associations
+        );
+        assert_eq!(config.config.handlers, exported.config.handlers);
+
+        // Regex handlers are not duplicated when imported twice
+        config.apply_import(
+            &mut buffer,
+            export(ExportFormat::Toml)?,
+            false,
+            false,
+            true,
+        )?;
+        assert_eq!(config.config.handlers.len(), 1);
+
+        // Replacing forgets everything that is not part of the import
+        config.apply_import(
+            &mut buffer,
+            export(ExportFormat::Toml)?,
+            false,
+            true,
+            true,
+        )?;
+        assert_eq!(
+            config.mime_apps.default_apps,
+            exported.mime_apps.default_apps
+        );
+
+        assert_eq!(
+            String::from_utf8(buffer)?.lines().collect::<Vec<_>>(),
+            ["Imported 2 default app(s), 1 added association(s) and 1 regex handler(s)"; 3]
+        );
+
+        // Nothing is changed if any handler is invalid
+        let import: AssociationsExport = serde_json::from_str(
+            r#"{ "default_apps": [{ "mime": "text/plain", "handlers": ["not-a-real-app.desktop"] }] }"#,
+        )?;
+        assert!(config
+            .apply_import(&mut Vec::new(), import, false, true, false)
+            .is_err());
+        assert_eq!(
+            config.mime_apps.default_apps,
+            exported.mime_apps.default_apps
+        );
+
+        Ok(())
+    }
 
     #[test]
-    fn wildcard_mimes() -> Result<()> {
+    fn import_merged_associations() -> Result<()> {
         let mut config = Config::default();
-        config.add_handler(
-            &Mime::from_str("video/*")?,
-            &DesktopHandler::assume_valid("mpv.desktop".into()),
-        )?;
-        config.add_handler(
-            &Mime::from_str("video/webm")?,
-            &DesktopHandler::assume_valid("brave.desktop".into()),
+        config.set_handlers(
+            &[mime::APPLICATION_PDF],
+            &DesktopHandler::assume_valid("mupdf.desktop".into()),
         )?;
 
-        assert_eq!(
+        let import = || -> Result<AssociationsExport> {
+            Ok(serde_json::from_str(
+                r#"{ "default_apps": [{ "mime": "application/x-pdf", "handlers": ["zathura.desktop", "mupdf.desktop"] }] }"#,
+            )?)
+        };
+        let handlers = |config: &Config| {
             config
-                .get_handler(&Mime::from_str("video/mp4")?)?
-                .to_string(),
-            "mpv.desktop"
+                .mime_apps
+                .default_apps
+                .iter()
+                .map(|(mime, handlers)| {
+                    format!("{mime}={}", handlers.iter().join(";"))
+                })
+                .collect_vec()
+        };
+
+        // Aliases are imported under their canonical name, and merged with its handlers
+        config.apply_import(&mut Vec::new(), import()?, true, false, true)?;
+        assert_eq!(
+            handlers(&config),
+            ["application/pdf=mupdf.desktop;zathura.desktop"]
         );
+
+        // Without merging, the imported handlers replace the existing ones
+        config.apply_import(&mut Vec::new(), import()?, false, false, true)?;
         assert_eq!(
-            config
-                .get_handler(&Mime::from_str("video/asdf")?)?
-                .to_string(),
-            "mpv.desktop"
+            handlers(&config),
+            ["application/pdf=zathura.desktop;mupdf.desktop"]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn ambiguous_extensions() -> Result<()> {
+        let config = Config::default();
+        let ts = MimeOrExtension::Extension(
+            ".ts".into(),
+            vec![
+                Mime::from_str("text/vnd.trolltech.linguist")?,
+                Mime::from_str("video/mp2t")?,
+            ],
         );
+
+        assert_eq!(config.resolve_mimes(&ts, true)?.len(), 2);
         assert_eq!(
             config
-                .get_handler(&Mime::from_str("video/webm")?)?
+                .resolve_mimes(&ts, false)
+                .expect_err("Ambiguous extension should be rejected")
                 .to_string(),
-            "brave.desktop"
+            "'.ts' could refer to any of text/vnd.trolltech.linguist, video/mp2t, use one of them, --all-candidates, or enable the selector"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn show_group_handlers() -> Result<()> {
+        let mut config = Config::default();
+        config.add_handlers(
+            &[Mime::from_str("x-scheme-handler/https")?],
+            &DesktopHandler::from_str("tests/firefox.desktop")?,
+        )?;
+        config.add_handlers(
+            &[Mime::from_str("text/html")?],
+            &DesktopHandler::from_str("tests/firefox.desktop")?,
+        )?;
+
+        let mut buffer = Vec::new();
+        config.show_group_handlers(
+            &mut buffer,
+            &MimeOrExtension::from_str("@browser")?,
+            false,
+        )?;
+        goldie::assert!(String::from_utf8(buffer)?);
+
+        assert!(config
+            .show_group_handlers(
+                &mut Vec::new(),
+                &MimeOrExtension::from_str("@mail")?,
+                false
+            )
+            .is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn show_ambiguous_extension_handlers() -> Result<()> {
+        let mut config = Config::default();
+        config.add_handlers(
+            &[Mime::from_str("video/mp2t")?],
+            &DesktopHandler::from_str("tests/mpv.desktop")?,
+        )?;
+        config.add_handlers(
+            &[Mime::from_str("text/vnd.trolltech.linguist")?],
+            &DesktopHandler::from_str("tests/Helix.desktop")?,
+        )?;
+
+        let ts = MimeOrExtension::Extension(
+            ".ts".into(),
+            vec![
+                Mime::from_str("text/vnd.trolltech.linguist")?,
+                Mime::from_str("video/mp2t")?,
+            ],
+        );
+
+        let mut buffer = Vec::new();
+        config.show_group_handlers(&mut buffer, &ts, false)?;
+        goldie::assert!(String::from_utf8(buffer)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn check_handlers() -> Result<()> {
+        let mut config = Config::default();
+        config
+            .system_apps
+            .add_unassociated(DesktopHandler::from_str("tests/cmus.desktop")?);
+
+        let audio = Mime::from_str("audio/ogg")?;
+
+        config.check_handler(
+            &audio,
+            &DesktopHandler::from_str("tests/cmus.desktop")?,
+            false,
+        )?;
+        config.check_handler(
+            &audio,
+            &DesktopHandler::from_str("tests/cmsu.desktop")?,
+            true,
+        )?;
+
+        let error = config
+            .check_handler(
+                &audio,
+                &DesktopHandler::from_str("tests/cmsu.desktop")?,
+                false,
+            )
+            .expect_err("Mistyped handler should be rejected");
+        assert_eq!(
+            error.to_string(),
+            "unknown handler 'tests/cmsu.desktop' (did you mean 'tests/cmus.desktop'?), use --force to use it anyway"
         );
 
+        // Exists, but cannot be parsed
+        assert!(config
+            .check_handler(
+                &audio,
+                &DesktopHandler::from_str("tests/random.bin")?,
+                false
+            )
+            .is_err());
+
         Ok(())
     }
 
     #[test]
     fn complex_wildcard_mimes() -> Result<()> {
         let mut config = Config::default();
-        config.add_handler(
-            &Mime::from_str("application/vnd.oasis.opendocument.*")?,
-            &DesktopHandler::assume_valid("startcenter.desktop".into()),
+        config.add_handlers(
+            &[Mime::from_str("application/vnd.oasis.opendocument.*")?],
+            &DesktopHandler::from_str("tests/startcenter.desktop")?,
         )?;
-        config.add_handler(
-            &Mime::from_str("application/vnd.openxmlformats-officedocument.*")?,
-            &DesktopHandler::assume_valid("startcenter.desktop".into()),
+        config.add_handlers(
+            &[Mime::from_str(
+                "application/vnd.openxmlformats-officedocument.*",
+            )?],
+            &DesktopHandler::from_str("tests/startcenter.desktop")?,
         )?;
 
         assert_eq!(
@@ -428,7 +2781,7 @@ mod tests {
                     "application/vnd.oasis.opendocument.text"
                 )?,)?
                 .to_string(),
-            "startcenter.desktop"
+            "tests/startcenter.desktop"
         );
         assert_eq!(
             config
@@ -436,7 +2789,7 @@ mod tests {
                     &Mime::from_str("application/vnd.openxmlformats-officedocument.spreadsheetml.sheet")?,
                 )?
                 .to_string(),
-            "startcenter.desktop"
+            "tests/startcenter.desktop"
         );
 
         Ok(())
@@ -454,40 +2807,42 @@ mod tests {
         let mut config = Config::default();
 
         // Add arbitrary video handlers
-        config.add_handler(
-            &Mime::from_str("video/mp4")?,
+        config.add_handlers(
+            &[Mime::from_str("video/mp4")?],
             &DesktopHandler::assume_valid("mpv.desktop".into()),
         )?;
-        config.add_handler(
-            &Mime::from_str("video/asdf")?,
+        config.add_handlers(
+            &[Mime::from_str("video/asdf")?],
             &DesktopHandler::assume_valid("mpv.desktop".into()),
         )?;
-        config.add_handler(
-            &Mime::from_str("video/webm")?,
+        config.add_handlers(
+            &[Mime::from_str("video/webm")?],
             &DesktopHandler::assume_valid("brave.desktop".into()),
         )?;
 
         // Add arbitrary text handlers
-        config.add_handler(
-            &mime::TEXT_PLAIN,
+        config.add_handlers(
+            &[mime::TEXT_PLAIN],
             &DesktopHandler::assume_valid("helix.desktop".into()),
         )?;
-        config.add_handler(
-            &mime::TEXT_PLAIN,
+        config.add_handlers(
+            &[mime::TEXT_PLAIN],
             &DesktopHandler::assume_valid("nvim.desktop".into()),
         )?;
-        config.add_handler(
-            &mime::TEXT_PLAIN,
+        config.add_handlers(
+            &[mime::TEXT_PLAIN],
             &DesktopHandler::assume_valid("kakoune.desktop".into()),
         )?;
 
         // Add arbitrary document handlers
-        config.add_handler(
-            &Mime::from_str("application/vnd.oasis.opendocument.*")?,
+        config.add_handlers(
+            &[Mime::from_str("application/vnd.oasis.opendocument.*")?],
             &DesktopHandler::assume_valid("startcenter.desktop".into()),
         )?;
-        config.add_handler(
-            &Mime::from_str("application/vnd.openxmlformats-officedocument.*")?,
+        config.add_handlers(
+            &[Mime::from_str(
+                "application/vnd.openxmlformats-officedocument.*",
+            )?],
             &DesktopHandler::assume_valid("startcenter.desktop".into()),
         )?;
 
@@ -577,8 +2932,8 @@ mod tests {
     fn terminal_command_set() -> Result<()> {
         let mut config = Config::default();
 
-        config.add_handler(
-            &Mime::from_str("x-scheme-handler/terminal")?,
+        config.add_handlers(
+            &[Mime::from_str("x-scheme-handler/terminal")?],
             &DesktopHandler::from_str("tests/org.wezfurlong.wezterm.desktop")?,
         )?;
 
@@ -613,14 +2968,14 @@ mod tests {
         };
 
         // Use actual desktop file because command may be needed
-        config.add_handler(
-            &mime::TEXT_PLAIN,
+        config.add_handlers(
+            &[mime::TEXT_PLAIN],
             &DesktopHandler::from_str("tests/Helix.desktop")?,
         )?;
 
         // May be needed if terminal command is needed
-        config.add_handler(
-            &Mime::from_str("x-scheme-handler/terminal")?,
+        config.add_handlers(
+            &[Mime::from_str("x-scheme-handler/terminal")?],
             &DesktopHandler::from_str("tests/org.wezfurlong.wezterm.desktop")?,
         )?;
 
@@ -667,46 +3022,46 @@ mod tests {
     }
 
     fn test_add_handlers(config: &mut Config) -> Result<()> {
-        config.add_handler(
-            &mime::TEXT_PLAIN,
-            &DesktopHandler::assume_valid("Helix.desktop".into()),
+        config.add_handlers(
+            &[mime::TEXT_PLAIN],
+            &DesktopHandler::from_str("tests/Helix.desktop")?,
         )?;
 
         // Should return first added handler
         assert_eq!(
             config.get_handler(&mime::TEXT_PLAIN)?.to_string(),
-            "Helix.desktop"
+            "tests/Helix.desktop"
         );
 
-        config.add_handler(
-            &mime::TEXT_PLAIN,
-            &DesktopHandler::assume_valid("nvim.desktop".into()),
+        config.add_handlers(
+            &[mime::TEXT_PLAIN],
+            &DesktopHandler::from_str("tests/nvim.desktop")?,
         )?;
 
         // Should still return first added handler
         assert_eq!(
             config.get_handler(&mime::TEXT_PLAIN)?.to_string(),
-            "Helix.desktop"
+            "tests/Helix.desktop"
         );
 
         Ok(())
     }
 
     fn test_remove_handlers(config: &mut Config) -> Result<()> {
-        config.remove_handler(
-            &mime::TEXT_PLAIN,
-            &DesktopHandler::assume_valid("Helix.desktop".into()),
+        config.remove_handlers(
+            &[mime::TEXT_PLAIN],
+            &DesktopHandler::from_str("tests/Helix.desktop")?,
         )?;
 
         // With first added handler removed, second handler replaces it
         assert_eq!(
             config.get_handler(&mime::TEXT_PLAIN)?.to_string(),
-            "nvim.desktop"
+            "tests/nvim.desktop"
         );
 
-        config.remove_handler(
-            &mime::TEXT_PLAIN,
-            &DesktopHandler::assume_valid("nvim.desktop".into()),
+        config.remove_handlers(
+            &[mime::TEXT_PLAIN],
+            &DesktopHandler::from_str("tests/nvim.desktop")?,
         )?;
 
         // Both handlers removed, should not be any left
@@ -716,32 +3071,32 @@ mod tests {
     }
 
     fn test_set_handlers(config: &mut Config) -> Result<()> {
-        config.set_handler(
-            &mime::TEXT_PLAIN,
-            &DesktopHandler::assume_valid("Helix.desktop".into()),
+        config.set_handlers(
+            &[mime::TEXT_PLAIN],
+            &DesktopHandler::from_str("tests/Helix.desktop")?,
         )?;
 
         assert_eq!(
             config.get_handler(&mime::TEXT_PLAIN)?.to_string(),
-            "Helix.desktop"
+            "tests/Helix.desktop"
         );
 
-        config.set_handler(
-            &mime::TEXT_PLAIN,
-            &DesktopHandler::assume_valid("nvim.desktop".into()),
+        config.set_handlers(
+            &[mime::TEXT_PLAIN],
+            &DesktopHandler::from_str("tests/nvim.desktop")?,
         )?;
 
         // Should return second set handler because it should replace the first one
         assert_eq!(
             config.get_handler(&mime::TEXT_PLAIN)?.to_string(),
-            "nvim.desktop"
+            "tests/nvim.desktop"
         );
 
         Ok(())
     }
 
     fn test_unset_handlers(config: &mut Config) -> Result<()> {
-        config.unset_handler(&mime::TEXT_PLAIN)?;
+        config.unset_handlers(&[mime::TEXT_PLAIN])?;
 
         // Handler completely unset, should not be any left
         assert!(config.get_handler(&mime::TEXT_PLAIN).is_err());
@@ -789,6 +3144,112 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn handler_candidates() -> Result<()> {
+        let mut config = Config::default();
+
+        config
+            .system_apps
+            .add_unassociated(DesktopHandler::from_str(
+                "tests/org.wezfurlong.wezterm.desktop",
+            )?);
+        config
+            .system_apps
+            .associations
+            .entry(mime::TEXT_PLAIN)
+            .or_default()
+            .push_back(DesktopHandler::from_str("tests/Helix.desktop")?);
+
+        let candidates = |all_apps| -> Result<Vec<(String, String)>> {
+            Ok(config
+                .handler_candidates(&Mime::from_str("text/markdown")?, all_apps)
+                .into_iter()
+                .map(|(h, name)| (h.to_string(), name))
+                .collect_vec())
+        };
+
+        // Apps for parent mimes come first, then every other app
+        assert_eq!(
+            candidates(true)?,
+            [
+                ("tests/Helix.desktop", "Helix"),
+                ("tests/org.wezfurlong.wezterm.desktop", "WezTerm")
+            ]
+            .map(|(h, name)| (h.to_string(), name.to_string()))
+        );
+        assert_eq!(
+            candidates(false)?,
+            [("tests/Helix.desktop".to_string(), "Helix".to_string())]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn fallback_handlers() -> Result<()> {
+        let mut config = Config::default();
+
+        // Without fallbacks, paths with no handlers should fail
+        assert!(config
+            .get_handler_from_path(&UserPath::from_str("tests/random.bin")?)
+            .is_err());
+        assert!(config
+            .get_handler_from_path(&UserPath::from_str("tests/rust.vim")?)
+            .is_err());
+
+        config.config.fallback.file =
+            Some(DesktopHandler::assume_valid("hexyl.desktop".into()));
+        config.config.fallback.text =
+            Some(DesktopHandler::from_str("tests/Helix.desktop")?);
+        config.config.fallback.url =
+            Some(DesktopHandler::from_str("tests/firefox.desktop")?);
+        config.add_handlers(
+            &[mime::TEXT_HTML],
+            &DesktopHandler::from_str("tests/nvim.desktop")?,
+        )?;
+
+        let handler = |config: &mut Config, path| -> Result<Handler> {
+            Ok(config.get_handler_from_path(&UserPath::from_str(path)?)?.0)
+        };
+
+        // Ambiguous binary file
+        assert_eq!(
+            handler(&mut config, "tests/random.bin")?,
+            Handler::new("hexyl.desktop")
+        );
+        // Text file with no handler
+        assert_eq!(
+            handler(&mut config, "tests/rust.vim")?,
+            Handler::new("tests/Helix.desktop")
+        );
+        // URL with no handler
+        assert_eq!(
+            handler(&mut config, "https://duckduckgo.com")?,
+            Handler::new("tests/firefox.desktop")
+        );
+        // Fallbacks should not override existing handlers
+        assert_eq!(
+            handler(&mut config, "tests/p.html")?,
+            Handler::new("tests/nvim.desktop")
+        );
+
+        // Only handlers found through a mime come with it
+        let source = |config: &mut Config, path| -> Result<Option<Mime>> {
+            Ok(config.get_handler_from_path(&UserPath::from_str(path)?)?.1)
+        };
+        assert_eq!(source(&mut config, "tests/p.html")?, Some(mime::TEXT_HTML));
+        assert_eq!(source(&mut config, "tests/random.bin")?, None);
+
+        // Text files should use the file fallback if there is no text fallback
+        config.config.fallback.text = None;
+        assert_eq!(
+            handler(&mut config, "tests/rust.vim")?,
+            Handler::new("hexyl.desktop")
+        );
+
+        Ok(())
+    }
+
     #[test]
     fn override_selector() -> Result<()> {
         let mut config = Config::default();
@@ -876,20 +3337,24 @@ mod tests {
     #[test]
     fn properly_assign_files_to_handlers() -> Result<()> {
         let mut config = Config::default();
-        config.add_handler(
-            &Mime::from_str("image/png")?,
-            &DesktopHandler::assume_valid("swayimg.desktop".into()),
+        config.add_handlers(
+            &[Mime::from_str("image/png")?],
+            &DesktopHandler::from_str("tests/swayimg.desktop")?,
         )?;
-        config.add_handler(
-            &Mime::from_str("application/pdf")?,
-            &DesktopHandler::assume_valid("mupdf.desktop".into()),
+        config.add_handlers(
+            &[Mime::from_str("application/pdf")?],
+            &DesktopHandler::from_str("tests/mupdf.desktop")?,
         )?;
 
         let mut expected_handlers = HashMap::new();
-        expected_handlers
-            .insert(Handler::new("swayimg.desktop"), vec!["a.png".to_owned()]);
-        expected_handlers
-            .insert(Handler::new("mupdf.desktop"), vec!["a.pdf".to_owned()]);
+        expected_handlers.insert(
+            Handler::new("tests/swayimg.desktop"),
+            vec![("a.png".to_owned(), Some(mime::IMAGE_PNG))],
+        );
+        expected_handlers.insert(
+            Handler::new("tests/mupdf.desktop"),
+            vec![("a.pdf".to_owned(), Some(mime::APPLICATION_PDF))],
+        );
 
         assert_eq!(
             config.assign_files_to_handlers(&[
@@ -909,11 +3374,16 @@ mod tests {
 
         let mut expected_handlers = HashMap::new();
         expected_handlers.insert(
-            Handler::new("swayimg.desktop"),
-            vec!["a.png".to_owned(), "b.png".to_owned()],
+            Handler::new("tests/swayimg.desktop"),
+            vec![
+                ("a.png".to_owned(), Some(mime::IMAGE_PNG)),
+                ("b.png".to_owned(), Some(mime::IMAGE_PNG)),
+            ],
+        );
+        expected_handlers.insert(
+            Handler::new("tests/mupdf.desktop"),
+            vec![("a.pdf".to_owned(), Some(mime::APPLICATION_PDF))],
         );
-        expected_handlers
-            .insert(Handler::new("mupdf.desktop"), vec!["a.pdf".to_owned()]);
 
         assert_eq!(
             config.assign_files_to_handlers(&[
@@ -1,5 +1,5 @@
 mod config_file;
 mod main_config;
 
-pub use config_file::ConfigFile;
+pub use config_file::{ConfigFile, HandlerOrder};
 pub use main_config::Config;
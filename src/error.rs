@@ -15,6 +15,26 @@ pub enum Error {
     BadMimeType(#[from] mime::FromStrError),
     #[error("bad mime: {0}")]
     InvalidMime(mime::Mime),
+    #[error("unknown mime '{0}'{}, use --force to use it anyway", format_suggestions(.1))]
+    UnknownMime(mime::Mime, Vec<String>),
+    #[error("'{0}' could refer to any of {}, use one of them, --all-candidates, or enable the selector", .1.join(", "))]
+    AmbiguousExtension(String, Vec<String>),
+    #[error("'{0}' could refer to any of {}, use one of them or enable the selector", .1.join(", "))]
+    AmbiguousLaunch(String, Vec<String>),
+    #[error("unknown mime group '@{0}'{}", format_suggestions(.1))]
+    UnknownGroup(String, Vec<String>),
+    #[error("mime group '@{0}' has no mimes")]
+    EmptyGroup(String),
+    #[error("unknown handler '{0}'{}, use --force to use it anyway", format_suggestions(.1))]
+    UnknownHandler(crate::common::DesktopHandler, Vec<String>),
+    #[error("'{0}' could refer to any of {}, use one of their desktop IDs or enable the selector", .1.join(", "))]
+    AmbiguousHandler(String, Vec<String>),
+    #[error("{} not among the default handlers of '{1}'", .0.iter().map(|h| format!("'{h}'")).collect::<Vec<_>>().join(", "))]
+    NotAssociated(Vec<String>, mime::Mime),
+    #[error("nothing to {0}")]
+    NoHistory(&'static str),
+    #[error("nothing to reset{}", .0.as_ref().map(|p| format!(", no associations match '{p}'")).unwrap_or_default())]
+    NothingToReset(Option<String>),
     #[error("malformed desktop entry at {0}")]
     BadEntry(std::path::PathBuf),
     #[error(transparent)]
@@ -25,14 +45,20 @@ pub enum Error {
     Cancelled,
     #[error("Please specify the default terminal with handlr set x-scheme-handler/terminal")]
     NoTerminal,
+    #[error("could not open '{}': {1}", .0.display())]
+    OpenFile(std::path::PathBuf, #[source] std::io::Error),
     #[error("Bad path: {0}")]
     BadPath(String),
     #[error(transparent)]
     SerdeJson(#[from] serde_json::Error),
     #[error(transparent)]
-    SerdeIniDe(#[from] serde_ini::de::Error),
+    TomlDe(#[from] toml::de::Error),
     #[error(transparent)]
-    SerdeIniSer(#[from] serde_ini::ser::Error),
+    TomlSer(#[from] toml::ser::Error),
+    #[error(transparent)]
+    TomlEdit(#[from] toml_edit::TomlError),
+    #[error("cannot import '{0}', only json and toml files can be imported (use --format to specify which)")]
+    UnsupportedImport(std::path::PathBuf),
     #[error("Could not split exec command '{0}' in desktop file '{1}' into shell words")]
     BadExec(String, String),
     #[error("Could not split command '{0}' into shell words")]
@@ -46,3 +72,19 @@ pub enum Error {
 }
 
 pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Format suggestions for an error message, if there are any
+fn format_suggestions(suggestions: &[String]) -> String {
+    if suggestions.is_empty() {
+        String::new()
+    } else {
+        format!(
+            " (did you mean {}?)",
+            suggestions
+                .iter()
+                .map(|s| format!("'{s}'"))
+                .collect::<Vec<_>>()
+                .join(", ")
+        )
+    }
+}
@@ -5,8 +5,9 @@ mod config;
 mod error;
 mod utils;
 
-use cli::Cmd;
-use common::mime_table;
+use apps::{MimeApps, Reorder};
+use cli::{Cli, Cmd};
+use common::{mime_table, MimeOrExtension};
 use config::Config;
 use error::Result;
 
@@ -15,29 +16,84 @@ use clap_complete::CompleteEnv;
 
 #[mutants::skip] // Cannot test directly at the moment
 fn main() -> Result<()> {
-    CompleteEnv::with_factory(|| Cmd::command().name("handlr")).completer("handlr").complete();
+    CompleteEnv::with_factory(|| Cli::command().name("handlr"))
+        .completer("handlr")
+        .complete();
 
-    let mut config = Config::new()?;
+    let Cli {
+        mimeapps,
+        root,
+        command: cmd,
+    } = Cli::parse();
+
+    if let Some(root) = root {
+        utils::use_xdg_root(&root)?;
+    }
+
+    // Lock mimeapps.list before reading it, so that concurrent modifications are not lost
+    let modifies = modifies_mimeapps(&cmd);
+    let _lock = modifies
+        .then(|| MimeApps::lock(mimeapps.as_deref()))
+        .transpose()?;
+
+    let mut config = Config::new(mimeapps, modifies)?;
     let mut stdout = std::io::stdout().lock();
 
-    let res = match Cmd::parse() {
-        Cmd::Set { mime, handler } => config.set_handler(&mime, &handler),
-        Cmd::Add { mime, handler } => config.add_handler(&mime, &handler),
-        Cmd::Launch {
+    let res = match cmd {
+        Cmd::Set {
             mime,
+            handler,
+            all_apps,
+            all_candidates,
+            force,
+            selector_args,
+        } => {
+            config.override_selector(selector_args);
+            config.set_handler_checked(
+                &mime,
+                handler.as_ref(),
+                all_apps,
+                all_candidates,
+                force,
+            )
+        }
+        Cmd::Add {
+            mime,
+            handler,
+            all_candidates,
+            force,
+        } => config.add_handler_checked(&mime, &handler, all_candidates, force),
+        Cmd::Launch {
+            target,
             args,
             selector_args,
         } => {
             config.override_selector(selector_args);
-            config.launch_handler(&mime, args)
+            config.launch(&target, args)
         }
         Cmd::Get {
             mime,
             json,
+            all,
+            path,
             selector_args,
         } => {
             config.override_selector(selector_args);
-            config.show_handler(&mut stdout, &mime, json)
+            match mime {
+                Some(MimeOrExtension::Mime(mime)) if !all => {
+                    config.show_handler(&mut stdout, &mime, json)
+                }
+                Some(group) if !all => {
+                    config.show_group_handlers(&mut stdout, &group, json)
+                }
+                // Without a mime, `--path` is given, which requires `--all`
+                mime => config.show_handler_candidates(
+                    &mut stdout,
+                    mime.as_ref(),
+                    path.as_ref(),
+                    json,
+                ),
+            }
         }
         Cmd::Open {
             paths,
@@ -49,9 +105,102 @@ fn main() -> Result<()> {
         Cmd::Mime { paths, json } => {
             mime_table(&mut stdout, &paths, json, config.terminal_output)
         }
-        Cmd::List { all, json } => config.print(&mut stdout, all, json),
-        Cmd::Unset { mime } => config.unset_handler(&mime),
-        Cmd::Remove { mime, handler } => config.remove_handler(&mime, &handler),
+        Cmd::List { all, json, handler } => match handler {
+            Some(handler) => {
+                config.resolve_handler(&handler).and_then(|handler| {
+                    config.print_handler_mimes(&mut stdout, &handler, json)
+                })
+            }
+            None => config.print(&mut stdout, all, json),
+        },
+        Cmd::SetDefaultApp {
+            handler,
+            filter,
+            dry_run,
+        } => config.resolve_handler(&handler).and_then(|handler| {
+            config.set_default_app(
+                &mut stdout,
+                &handler,
+                filter.as_deref(),
+                dry_run,
+            )
+        }),
+        Cmd::Unset {
+            mime,
+            all_candidates,
+        } => config
+            .resolve_mimes(&mime, all_candidates)
+            .and_then(|mimes| config.unset_handlers(&mimes)),
+        Cmd::Remove {
+            mime,
+            handler,
+            all_candidates,
+        } => config.resolve_handler(&handler).and_then(|handler| {
+            let mimes = config.resolve_mimes(&mime, all_candidates)?;
+            config.remove_handlers(&mimes, &handler)
+        }),
+        Cmd::Promote {
+            mime,
+            handler,
+            all_candidates,
+        } => config.resolve_handler(&handler).and_then(|handler| {
+            config.reorder_handlers(
+                &mime,
+                &Reorder::Promote(handler),
+                all_candidates,
+            )
+        }),
+        Cmd::Demote {
+            mime,
+            handler,
+            all_candidates,
+        } => config.resolve_handler(&handler).and_then(|handler| {
+            config.reorder_handlers(
+                &mime,
+                &Reorder::Demote(handler),
+                all_candidates,
+            )
+        }),
+        Cmd::Reorder {
+            mime,
+            handlers,
+            all_candidates,
+        } => handlers
+            .iter()
+            .map(|handler| config.resolve_handler(handler))
+            .collect::<Result<Vec<_>>>()
+            .and_then(|handlers| {
+                config.reorder_handlers(
+                    &mime,
+                    &Reorder::Order(handlers),
+                    all_candidates,
+                )
+            }),
+        Cmd::Reset { mime, .. } => config.reset(&mut stdout, mime.as_deref()),
+        Cmd::Diff { against, json } => {
+            config.diff(&mut stdout, against.as_deref(), json)
+        }
+        Cmd::Export { format } => config.export(&mut stdout, format),
+        Cmd::Import {
+            file,
+            format,
+            merge,
+            replace,
+            force,
+        } => config.import(&mut stdout, &file, format, merge, replace, force),
+        Cmd::History { json } => config.print_history(&mut stdout, json),
+        Cmd::Undo { n } => config.undo(&mut stdout, n),
+        Cmd::Redo { n } => config.redo(&mut stdout, n),
+        Cmd::Apps { search, mime, json } => config.print_apps(
+            &mut stdout,
+            search.as_deref(),
+            mime.as_ref(),
+            json,
+        ),
+        Cmd::NormalizeAliases => config.normalize_aliases(&mut stdout),
+        Cmd::Stats { mime, json } => {
+            config.print_stats(&mut stdout, mime.as_ref(), json)
+        }
     };
 
     // Issue a notification if handlr is not being run in a terminal
@@ -63,3 +212,24 @@ fn main() -> Result<()> {
 
     res
 }
+
+/// Whether a command modifies mimeapps.list, and so needs to lock it
+#[mutants::skip] // Trivial
+fn modifies_mimeapps(cmd: &Cmd) -> bool {
+    matches!(
+        cmd,
+        Cmd::Set { .. }
+            | Cmd::SetDefaultApp { dry_run: false, .. }
+            | Cmd::Unset { .. }
+            | Cmd::Add { .. }
+            | Cmd::Remove { .. }
+            | Cmd::Promote { .. }
+            | Cmd::Demote { .. }
+            | Cmd::Reorder { .. }
+            | Cmd::NormalizeAliases
+            | Cmd::Import { .. }
+            | Cmd::Reset { .. }
+            | Cmd::Undo { .. }
+            | Cmd::Redo { .. }
+    )
+}
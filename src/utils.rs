@@ -1,4 +1,14 @@
-use crate::error::Result;
+use crate::error::{Error, Result};
+use itertools::Itertools;
+use std::{
+    fs::File,
+    io::{BufRead, Write},
+    path::Path,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+/// Whether the XDG base directories have been relocated with `use_xdg_root`
+static XDG_RELOCATED: AtomicBool = AtomicBool::new(false);
 
 /// Issue a notification
 #[mutants::skip] // Cannot test directly, runs command
@@ -8,3 +18,289 @@ pub fn notify(title: &str, msg: &str) -> Result<()> {
         .spawn()?;
     Ok(())
 }
+
+/// Print a warning to stderr
+pub fn warn(msg: &str) {
+    eprintln!("warning: {msg}")
+}
+
+/// Relocate every XDG base directory into a given root, which stands in for both the home directory and `/`
+/// Must be called before anything reads the XDG environment variables
+#[mutants::skip] // Cannot test directly, alters the environment of the whole process
+pub fn use_xdg_root(root: &Path) -> Result<()> {
+    // The XDG spec requires absolute paths, relative ones are ignored
+    let root = std::path::absolute(root)?;
+
+    for (var, dir) in [
+        ("XDG_CONFIG_HOME", ".config"),
+        ("XDG_DATA_HOME", ".local/share"),
+        ("XDG_STATE_HOME", ".local/state"),
+        ("XDG_CACHE_HOME", ".cache"),
+        ("XDG_CONFIG_DIRS", "etc/xdg"),
+        ("XDG_DATA_DIRS", "usr/share"),
+    ] {
+        std::env::set_var(var, root.join(dir));
+    }
+    XDG_RELOCATED.store(true, Ordering::Relaxed);
+
+    Ok(())
+}
+
+/// Check whether the XDG base directories have been relocated with `use_xdg_root`
+pub fn xdg_relocated() -> bool {
+    XDG_RELOCATED.load(Ordering::Relaxed)
+}
+
+/// Get the current unix timestamp
+pub fn now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+/// Display how long ago a given unix timestamp was
+pub fn time_ago(time: u64) -> String {
+    let elapsed = now().saturating_sub(time);
+
+    match elapsed {
+        0..=59 => "just now".to_string(),
+        60..=3599 => format!("{} min ago", elapsed / 60),
+        3600..=86399 => format!("{} h ago", elapsed / 3600),
+        _ => format!("{} d ago", elapsed / 86400),
+    }
+}
+
+/// Write to a file atomically, so that it is never left empty or half-written
+/// The contents are written to a temporary file in the same directory,
+/// synced to disk, and then renamed over the original
+pub fn write_atomic<F>(path: &Path, write: F) -> Result<()>
+where
+    F: FnOnce(&mut File) -> Result<()>,
+{
+    // Write through symlinks rather than replacing them, e.g. for managed dotfiles
+    let path = std::fs::canonicalize(path).unwrap_or_else(|_| path.to_owned());
+    let dir = path
+        .parent()
+        .ok_or_else(|| Error::BadPath(path.display().to_string()))?;
+    std::fs::create_dir_all(dir)?;
+
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| Error::BadPath(path.display().to_string()))?;
+    let tmp_path = dir.join(format!(
+        ".{}.{}.tmp",
+        file_name.to_string_lossy(),
+        std::process::id()
+    ));
+
+    let res = (|| -> Result<()> {
+        let mut file = File::create(&tmp_path)?;
+        write(&mut file)?;
+
+        // Keep the original file's permissions
+        if let Ok(metadata) = std::fs::metadata(&path) {
+            file.set_permissions(metadata.permissions())?;
+        }
+
+        file.sync_all()?;
+        std::fs::rename(&tmp_path, &path)?;
+
+        // Make sure the rename itself is persisted
+        File::open(dir)?.sync_all()?;
+        Ok(())
+    })();
+
+    if res.is_err() {
+        let _ = std::fs::remove_file(&tmp_path);
+    }
+
+    res
+}
+
+/// Get the candidates that are closest to a given input, best matches first
+/// Meant for suggesting corrections for typos
+pub fn did_you_mean<I: IntoIterator<Item = String>>(
+    input: &str,
+    candidates: I,
+) -> Vec<String> {
+    let input = input.to_lowercase();
+    // Allow more typos for longer inputs, but not too many
+    let max_distance = (input.chars().count() / 4).clamp(1, 3);
+
+    candidates
+        .into_iter()
+        .map(|c| (strsim::osa_distance(&input, &c.to_lowercase()), c))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .sorted()
+        .dedup()
+        .take(3)
+        .map(|(_, c)| c)
+        .collect_vec()
+}
+
+/// Run given selector command
+#[mutants::skip] // Cannot test directly, runs external command
+pub fn select<O: Iterator<Item = String>>(
+    selector: &str,
+    mut opts: O,
+) -> Result<String> {
+    use std::{
+        io::Read,
+        process::{Command, Stdio},
+    };
+
+    let process = {
+        let mut split = shlex::split(selector)
+            .ok_or_else(|| Error::BadCmd(selector.to_string()))?;
+        let (cmd, args) = (split.remove(0), split);
+        Command::new(cmd)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?
+    };
+
+    let output = {
+        process
+            .stdin
+            .ok_or_else(|| Error::Selector(selector.to_string()))?
+            .write_all(opts.join("\n").as_bytes())?;
+
+        let mut output = String::with_capacity(24);
+
+        process
+            .stdout
+            .ok_or_else(|| Error::Selector(selector.to_string()))?
+            .read_to_string(&mut output)?;
+
+        output.trim_end().to_owned()
+    };
+
+    if output.is_empty() {
+        Err(Error::Cancelled)
+    } else {
+        Ok(output)
+    }
+}
+
+/// Prompt the user to pick one of the given options from a numbered list in the terminal
+#[mutants::skip] // Cannot test directly, reads from stdin
+pub fn pick_in_terminal(options: &[String]) -> Result<String> {
+    pick_from_list(
+        &mut std::io::stdin().lock(),
+        &mut std::io::stderr().lock(),
+        options,
+    )
+}
+
+/// Internal helper function for `pick_in_terminal`
+/// Makes testing easier
+fn pick_from_list<R: BufRead, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    options: &[String],
+) -> Result<String> {
+    for (i, option) in options.iter().enumerate() {
+        writeln!(writer, "{:>3}) {option}", i + 1)?;
+    }
+
+    loop {
+        write!(writer, "Pick a number (leave empty to cancel): ")?;
+        writer.flush()?;
+
+        let mut input = String::new();
+        reader.read_line(&mut input)?;
+
+        match input.trim() {
+            "" => return Err(Error::Cancelled),
+            input => match input.parse::<usize>() {
+                Ok(n) if (1..=options.len()).contains(&n) => {
+                    return Ok(options[n - 1].clone())
+                }
+                _ => writeln!(writer, "Invalid choice: {input}")?,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn atomic_writes() -> Result<()> {
+        let dir = std::env::temp_dir()
+            .join(format!("handlr-atomic-{}", std::process::id()));
+        let path = dir.join("mimeapps.list");
+
+        write_atomic(&path, |file| Ok(file.write_all(b"first")?))?;
+        assert_eq!(std::fs::read_to_string(&path)?, "first");
+
+        write_atomic(&path, |file| Ok(file.write_all(b"second")?))?;
+        assert_eq!(std::fs::read_to_string(&path)?, "second");
+
+        // A failed write leaves the original untouched, with no leftovers
+        assert!(write_atomic(&path, |file| {
+            file.write_all(b"third")?;
+            Err(Error::Cancelled)
+        })
+        .is_err());
+        assert_eq!(std::fs::read_to_string(&path)?, "second");
+        assert_eq!(std::fs::read_dir(&dir)?.count(), 1);
+
+        std::fs::remove_dir_all(dir)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn suggestions() {
+        let candidates = ["text/plain", "text/html", "image/png", "text/x-c"]
+            .map(String::from);
+
+        assert_eq!(
+            did_you_mean("text/plian", candidates.clone()),
+            ["text/plain"]
+        );
+        assert_eq!(
+            did_you_mean("TEXT/HTLM", candidates.clone()),
+            ["text/html"]
+        );
+        assert_eq!(did_you_mean("text/x-cc", candidates.clone()), ["text/x-c"]);
+        assert!(did_you_mean("video/mp4", candidates).is_empty());
+    }
+
+    #[test]
+    fn pick_from_numbered_list() -> Result<()> {
+        let options = ["Helix", "Neovim", "Kakoune"].map(String::from);
+
+        let mut output = Vec::new();
+        assert_eq!(
+            pick_from_list(
+                &mut "0\nfoo\n2\n".as_bytes(),
+                &mut output,
+                &options
+            )?,
+            "Neovim"
+        );
+        assert_eq!(
+            String::from_utf8(output)?,
+            "  1) Helix\n  2) Neovim\n  3) Kakoune\n\
+            Pick a number (leave empty to cancel): Invalid choice: 0\n\
+            Pick a number (leave empty to cancel): Invalid choice: foo\n\
+            Pick a number (leave empty to cancel): "
+        );
+
+        assert!(matches!(
+            pick_from_list(&mut "\n".as_bytes(), &mut Vec::new(), &options),
+            Err(Error::Cancelled)
+        ));
+        assert!(matches!(
+            pick_from_list(&mut "".as_bytes(), &mut Vec::new(), &options),
+            Err(Error::Cancelled)
+        ));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,612 @@
+//! Hermetic tests of the handlr binary, using `--root` to run it against a fake XDG tree
+//! Unlike unit tests, these exercise the real lookup and save code paths
+
+use pretty_assertions::assert_eq;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::{Command, Output},
+};
+
+type Result<T = ()> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+/// Fake XDG tree for a single test
+struct Sandbox {
+    root: PathBuf,
+}
+
+impl Sandbox {
+    /// Create an empty sandbox, named after the test using it
+    fn new(name: &str) -> Result<Self> {
+        let root = Path::new(env!("CARGO_TARGET_TMPDIR")).join(name);
+
+        if root.exists() {
+            fs::remove_dir_all(&root)?;
+        }
+        fs::create_dir_all(&root)?;
+
+        // Errors are sent as notifications when not run in a terminal,
+        // so print them instead of relying on a notification daemon
+        let sandbox = Self { root };
+        sandbox.write("bin/notify-send", "#!/bin/sh\necho \"$4\" >&2\n")?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(
+                sandbox.root.join("bin/notify-send"),
+                fs::Permissions::from_mode(0o755),
+            )?;
+        }
+
+        Ok(sandbox)
+    }
+
+    /// Install a desktop entry from the fixtures as a system-wide app
+    fn install(&self, desktop_file: &str) -> Result<&Self> {
+        let applications = self.root.join("usr/share/applications");
+        fs::create_dir_all(&applications)?;
+        fs::copy(
+            Path::new("tests").join(desktop_file),
+            applications.join(desktop_file),
+        )?;
+        Ok(self)
+    }
+
+    /// Write a file at a given path inside the sandbox
+    fn write(&self, path: &str, contents: &str) -> Result<&Self> {
+        let path = self.root.join(path);
+        fs::create_dir_all(path.parent().ok_or("path has no parent")?)?;
+        fs::write(path, contents)?;
+        Ok(self)
+    }
+
+    /// Read a file at a given path inside the sandbox
+    fn read(&self, path: &str) -> Result<String> {
+        Ok(fs::read_to_string(self.root.join(path))?)
+    }
+
+    /// Get the absolute path of a given path inside the sandbox
+    fn path(&self, path: &str) -> Result<String> {
+        Ok(self
+            .root
+            .join(path)
+            .to_str()
+            .ok_or("path is not valid UTF-8")?
+            .to_string())
+    }
+
+    /// Run handlr with given arguments inside the sandbox
+    fn handlr(&self, args: &[&str]) -> Result<Output> {
+        Ok(Command::new(env!("CARGO_BIN_EXE_handlr"))
+            .arg("--root")
+            .arg(&self.root)
+            .args(args)
+            .env_remove("HANDLR_MIMEAPPS")
+            .env(
+                "PATH",
+                std::env::join_paths(
+                    std::iter::once(self.root.join("bin")).chain(
+                        std::env::split_paths(
+                            &std::env::var_os("PATH").unwrap_or_default(),
+                        ),
+                    ),
+                )?,
+            )
+            .output()?)
+    }
+
+    /// Run handlr with given arguments inside the sandbox, expecting it to succeed
+    fn run(&self, args: &[&str]) -> Result<String> {
+        let output = self.handlr(args)?;
+        if !output.status.success() {
+            return Err(format!(
+                "handlr {} failed: {}",
+                args.join(" "),
+                String::from_utf8_lossy(&output.stderr)
+            )
+            .into());
+        }
+        Ok(String::from_utf8(output.stdout)?)
+    }
+}
+
+const MIMEAPPS: &str = ".config/mimeapps.list";
+
+#[test]
+fn set_and_get_handlers() -> Result<()> {
+    let sandbox = Sandbox::new("set_and_get_handlers")?;
+    sandbox.install("Helix.desktop")?.install("cmus.desktop")?;
+
+    // Handlers are found among the installed apps
+    assert_eq!(sandbox.run(&["get", "text/plain"])?, "Helix.desktop\n");
+
+    sandbox.run(&["set", "audio/mp3", "cmus.desktop"])?;
+    sandbox.run(&["add", "audio/mp3", "Helix.desktop"])?;
+    assert_eq!(
+        sandbox.read(MIMEAPPS)?,
+        "[Default Applications]\naudio/mp3=cmus.desktop;Helix.desktop;\n"
+    );
+    assert_eq!(sandbox.run(&["get", "audio/mp3"])?, "cmus.desktop\n");
+
+    // Desktop entries that are not installed are rejected
+    assert!(!sandbox
+        .handlr(&["set", "text/plain", "nvim.desktop"])?
+        .status
+        .success());
+    assert!(!sandbox.read(MIMEAPPS)?.contains("nvim.desktop"));
+
+    Ok(())
+}
+
+#[test]
+fn skip_handlers_that_are_not_installed() -> Result<()> {
+    let sandbox = Sandbox::new("skip_handlers_that_are_not_installed")?;
+    sandbox.write(
+        MIMEAPPS,
+        "[Default Applications]\ntext/plain=nvim.desktop;Helix.desktop;\n",
+    )?;
+
+    // No handler is installed yet
+    assert!(!sandbox.handlr(&["get", "text/plain"])?.status.success());
+
+    sandbox.install("Helix.desktop")?;
+    assert_eq!(sandbox.run(&["get", "text/plain"])?, "Helix.desktop\n");
+
+    sandbox.install("nvim.desktop")?;
+    assert_eq!(sandbox.run(&["get", "text/plain"])?, "nvim.desktop\n");
+
+    Ok(())
+}
+
+#[test]
+fn keep_unrelated_lines() -> Result<()> {
+    let sandbox = Sandbox::new("keep_unrelated_lines")?;
+    sandbox.install("Helix.desktop")?.write(
+        MIMEAPPS,
+        &fs::read_to_string("tests/mimeapps_comments.list")?,
+    )?;
+
+    sandbox.run(&["set", "text/plain", "Helix.desktop"])?;
+
+    assert_eq!(
+        sandbox.read(MIMEAPPS)?,
+        fs::read_to_string("tests/mimeapps_comments.list")?.replace(
+            "text/plain=nvim.desktop;Helix.desktop;",
+            "text/plain=Helix.desktop;"
+        )
+    );
+
+    Ok(())
+}
+
+#[test]
+fn undo_and_redo() -> Result<()> {
+    let sandbox = Sandbox::new("undo_and_redo")?;
+    sandbox.install("Helix.desktop")?.install("cmus.desktop")?;
+
+    sandbox.run(&["set", "text/plain", "Helix.desktop"])?;
+    sandbox.run(&["set", "text/plain", "cmus.desktop", "--force"])?;
+    assert!(sandbox
+        .root
+        .join(".local/state/handlr/journal.json")
+        .exists());
+
+    sandbox.run(&["undo"])?;
+    assert_eq!(sandbox.run(&["get", "text/plain"])?, "Helix.desktop\n");
+
+    sandbox.run(&["redo"])?;
+    assert_eq!(sandbox.run(&["get", "text/plain"])?, "cmus.desktop\n");
+
+    let history: Vec<serde_json::Value> =
+        serde_json::from_str(&sandbox.run(&["history", "--json"])?)?;
+    assert_eq!(history.len(), 2);
+
+    Ok(())
+}
+
+#[test]
+fn one_journal_entry_per_command() -> Result<()> {
+    let sandbox = Sandbox::new("one_journal_entry_per_command")?;
+    sandbox.install("Helix.desktop")?;
+
+    // Every mime of the group is changed, but only saved once
+    sandbox.run(&["set", "@browser", "Helix.desktop"])?;
+
+    let history: Vec<serde_json::Value> =
+        serde_json::from_str(&sandbox.run(&["history", "--json"])?)?;
+    assert_eq!(history.len(), 1);
+    assert!(history[0]["changes"].as_array().ok_or("no changes")?.len() > 1);
+
+    Ok(())
+}
+
+#[test]
+fn builtin_groups() -> Result<()> {
+    let sandbox = Sandbox::new("builtin_groups")?;
+    sandbox.install("cmus.desktop")?;
+
+    // Built-in groups only use mimes handlr knows about without a mime database
+    sandbox.run(&["set", "@audio-player", "cmus.desktop"])?;
+    assert_eq!(sandbox.run(&["get", "audio/x-flac"])?, "cmus.desktop\n");
+
+    Ok(())
+}
+
+#[test]
+fn nothing_to_unset_or_remove() -> Result<()> {
+    let sandbox = Sandbox::new("nothing_to_unset_or_remove")?;
+    sandbox.install("Helix.desktop")?;
+    sandbox.run(&["set", "text/plain", "Helix.desktop"])?;
+    let before = sandbox.read(MIMEAPPS)?;
+
+    sandbox.run(&["remove", "text/html", "Helix.desktop"])?;
+    sandbox.run(&["unset", "text/html"])?;
+
+    assert_eq!(sandbox.read(MIMEAPPS)?, before);
+    let history: Vec<serde_json::Value> =
+        serde_json::from_str(&sandbox.run(&["history", "--json"])?)?;
+    assert_eq!(history.len(), 1);
+
+    Ok(())
+}
+
+#[test]
+fn reset_keeps_every_backup() -> Result<()> {
+    let sandbox = Sandbox::new("reset_keeps_every_backup")?;
+    sandbox.install("Helix.desktop")?.install("cmus.desktop")?;
+
+    let reset = |handler: &str| -> Result<PathBuf> {
+        sandbox.run(&["set", "audio/ogg", handler])?;
+        let output = sandbox.run(&["reset", "audio/ogg"])?;
+        let backup = output
+            .lines()
+            .find_map(|l| l.strip_prefix("Backed up mimeapps.list to "))
+            .ok_or("backup location is not printed")?;
+        Ok(PathBuf::from(backup))
+    };
+
+    let first = reset("cmus.desktop")?;
+    let second = reset("Helix.desktop")?;
+    assert_ne!(first, second);
+    assert!(fs::read_to_string(first)?.contains("cmus.desktop"));
+    assert!(fs::read_to_string(second)?.contains("Helix.desktop"));
+
+    // Nothing left to reset
+    let output = sandbox.handlr(&["reset", "audio/ogg"])?;
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)?
+        .contains("nothing to reset, no associations match 'audio/ogg'"));
+
+    Ok(())
+}
+
+#[test]
+fn record_usage_of_opened_files() -> Result<()> {
+    let sandbox = Sandbox::new("record_usage_of_opened_files")?;
+    sandbox
+        .write(
+            "usr/share/applications/noop.desktop",
+            "[Desktop Entry]\nType=Application\nName=Noop\nExec=true %f\n",
+        )?
+        .write("usr/share/mime/globs2", "50:text/x-vim:*.vim\n")?
+        .write("usr/share/mime/subclasses", "text/x-vim text/plain\n")?
+        .write("rust.vim", "")?;
+    sandbox.run(&["set", "text/plain", "noop.desktop"])?;
+
+    // The handler is found through the file's parent mime, so that is what it is used for
+    sandbox.run(&["open", &sandbox.path("rust.vim")?])?;
+
+    let stats: Vec<serde_json::Value> =
+        serde_json::from_str(&sandbox.run(&["stats", "--json"])?)?;
+    assert_eq!(stats.len(), 1);
+    assert_eq!(stats[0]["mime"], "text/plain");
+    assert_eq!(stats[0]["handler"], "noop.desktop");
+
+    Ok(())
+}
+
+#[test]
+fn import_regex_handlers() -> Result<()> {
+    let sandbox = Sandbox::new("import_regex_handlers")?;
+    sandbox
+        .write(
+            "handlers.json",
+            r#"{ "handlers": [{ "exec": "freetube %u", "regexes": ["youtu\\.be/.*"] }] }"#,
+        )?
+        .write(
+            ".config/handlr/handlr.toml",
+            "# Managed by hand\nenable_selector = true\n",
+        )?;
+
+    sandbox.run(&[
+        "import",
+        sandbox
+            .root
+            .join("handlers.json")
+            .to_str()
+            .ok_or("bad path")?,
+    ])?;
+
+    // Regex handlers are saved to the config file inside the sandbox,
+    // leaving the rest of it untouched
+    let config = sandbox.read(".config/handlr/handlr.toml")?;
+    assert!(
+        config.starts_with("# Managed by hand\nenable_selector = true\n"),
+        "{config}"
+    );
+    assert!(config.contains("exec = \"freetube %u\""), "{config}");
+
+    let export: serde_json::Value =
+        serde_json::from_str(&sandbox.run(&["export"])?)?;
+    assert_eq!(export["handlers"][0]["exec"], "freetube %u");
+
+    Ok(())
+}
+
+#[test]
+fn explicit_mimeapps_path() -> Result<()> {
+    let sandbox = Sandbox::new("explicit_mimeapps_path")?;
+    sandbox.install("Helix.desktop")?;
+    let other = sandbox.root.join("other.list");
+    let other = other.to_str().ok_or("bad path")?;
+
+    // Reading a missing file fails rather than creating it
+    let output = sandbox.handlr(&["--mimeapps", other, "get", "text/plain"])?;
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)?.contains(other));
+    assert!(!sandbox.root.join("other.list").exists());
+
+    // Modifying it creates it, locking it next to it
+    sandbox.run(&[
+        "--mimeapps",
+        other,
+        "set",
+        "text/plain",
+        "Helix.desktop",
+    ])?;
+    assert!(sandbox
+        .read("other.list")?
+        .contains("text/plain=Helix.desktop;"));
+    assert!(sandbox.root.join("other.list.lock").exists());
+    assert!(!sandbox.root.join(MIMEAPPS).exists());
+
+    // Missing directories are not created
+    let nested = sandbox.root.join("missing/other.list");
+    let nested = nested.to_str().ok_or("bad path")?;
+    let output = sandbox.handlr(&[
+        "--mimeapps",
+        nested,
+        "set",
+        "text/plain",
+        "Helix.desktop",
+    ])?;
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)?.contains("missing/other.list"));
+
+    Ok(())
+}
+
+#[test]
+fn relative_root() -> Result<()> {
+    let sandbox = Sandbox::new("relative_root")?;
+    sandbox.install("Helix.desktop")?;
+
+    let root = sandbox.root.file_name().ok_or("root has no name")?;
+    let output = Command::new(env!("CARGO_BIN_EXE_handlr"))
+        .current_dir(sandbox.root.parent().ok_or("root has no parent")?)
+        .env("HANDLR_ROOT", root)
+        .args(["set", "text/plain", "Helix.desktop"])
+        .output()?;
+
+    assert!(output.status.success());
+    assert!(sandbox
+        .read(MIMEAPPS)?
+        .contains("text/plain=Helix.desktop;"));
+
+    Ok(())
+}
+
+#[test]
+fn terminal_commands() -> Result<()> {
+    let sandbox = Sandbox::new("terminal_commands")?;
+    sandbox.install("Helix.desktop")?.write(
+        "usr/share/applications/foot.desktop",
+        "[Desktop Entry]\nName=Foot\nExec=foot\nType=Application\n\
+         Categories=System;TerminalEmulator;\n",
+    )?;
+
+    // Without a terminal set, any installed terminal emulator is used
+    assert_eq!(
+        sandbox.run(&["get", "text/plain", "--json"])?.trim(),
+        r#"{"cmd":"foot -e hx","handler":"Helix.desktop","name":"Helix"}"#
+    );
+
+    // A terminal that is set takes precedence
+    sandbox.install("org.wezfurlong.wezterm.desktop")?.run(&[
+        "set",
+        "x-scheme-handler/terminal",
+        "org.wezfurlong.wezterm.desktop",
+    ])?;
+    assert_eq!(
+        sandbox.run(&["get", "text/plain", "--json"])?.trim(),
+        r#"{"cmd":"wezterm start --cwd . -e hx","handler":"Helix.desktop","name":"Helix"}"#
+    );
+
+    Ok(())
+}
+
+#[test]
+fn check_handlers() -> Result<()> {
+    let sandbox = Sandbox::new("check_handlers")?;
+    sandbox
+        .install("cmus.desktop")?
+        .write("usr/share/applications/random.desktop", "\u{0}garbage")?;
+
+    sandbox.run(&["set", "audio/ogg", "cmus.desktop"])?;
+
+    // Mistyped handlers are rejected unless forced
+    let output = sandbox.handlr(&["set", "audio/ogg", "cmsu.desktop"])?;
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)?.contains(
+        "unknown handler 'cmsu.desktop' (did you mean 'cmus.desktop'?), use --force to use it anyway"
+    ));
+    sandbox.run(&["set", "audio/ogg", "cmsu.desktop", "--force"])?;
+
+    // Exists, but cannot be parsed
+    assert!(!sandbox
+        .handlr(&["set", "audio/ogg", "random.desktop"])?
+        .status
+        .success());
+
+    Ok(())
+}
+
+#[test]
+fn set_default_app() -> Result<()> {
+    let sandbox = Sandbox::new("set_default_app")?;
+    sandbox.install("Helix.desktop")?.install("cmus.desktop")?;
+    sandbox.run(&["set", "text/x-csrc", "cmus.desktop", "--force"])?;
+
+    assert_eq!(
+        sandbox.run(&[
+            "set-default-app",
+            "Helix.desktop",
+            "--filter",
+            "text/x-c*",
+            "--dry-run"
+        ])?,
+        "\
+mime         \tprevious    
+text/x-c++hdr\t            
+text/x-c++src\t            
+text/x-chdr  \t            
+text/x-csrc  \tcmus.desktop
+text/x-c     \t            
+text/x-c++   \t            
+Would set Helix.desktop as the default for 6 mime(s), 0 already set
+"
+    );
+
+    // Nothing changes on a dry run
+    assert_eq!(sandbox.run(&["get", "text/x-csrc"])?, "cmus.desktop\n");
+    assert!(!sandbox.read(MIMEAPPS)?.contains("text/x-c++hdr"));
+
+    sandbox.run(&["set-default-app", "Helix.desktop"])?;
+    for mime in ["text/x-csrc", "text/x-c++hdr", "application/x-shellscript"] {
+        assert_eq!(sandbox.run(&["get", mime])?, "Helix.desktop\n");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn handler_candidates() -> Result<()> {
+    let sandbox = Sandbox::new("handler_candidates")?;
+    sandbox
+        .install("Helix.desktop")?
+        .install("org.wezfurlong.wezterm.desktop")?;
+
+    // Record the candidates offered to the selector, and pick the first one
+    let candidates = sandbox.path("candidates")?;
+    let selector = format!("sh -c 'tee {candidates} | head -n 1'");
+    let pick = |all_apps: bool| -> Result<String> {
+        let mut args = vec![
+            "set",
+            "text/markdown",
+            "--force",
+            "--enable-selector",
+            "--selector",
+            &selector,
+        ];
+        if all_apps {
+            args.push("--all-apps");
+        }
+        sandbox.run(&args)?;
+        sandbox.read("candidates")
+    };
+
+    // Apps for parent mimes come first, then every other app
+    assert_eq!(
+        pick(true)?,
+        "Helix (Helix.desktop)\nWezTerm (org.wezfurlong.wezterm.desktop)"
+    );
+    assert_eq!(pick(false)?, "Helix (Helix.desktop)");
+    assert_eq!(sandbox.run(&["get", "text/markdown"])?, "Helix.desktop\n");
+
+    Ok(())
+}
+
+#[test]
+fn handler_candidates_with_sources() -> Result<()> {
+    let sandbox = Sandbox::new("handler_candidates_with_sources")?;
+    sandbox
+        .install("Helix.desktop")?
+        .install("org.wezfurlong.wezterm.desktop")?
+        .write("usr/share/mime/globs2", "50:text/x-vim:*.vim\n")?
+        .write("rust.vim", "")?
+        .write(
+            ".config/handlr/handlr.toml",
+            "[fallback]\nfile = \"hexyl.desktop\"\n",
+        )?;
+
+    sandbox.run(&["set", "text/markdown", "apostrophe.desktop", "--force"])?;
+    sandbox.run(&["add", "text/*", "Helix.desktop"])?;
+    sandbox.run(&[
+        "set",
+        "x-scheme-handler/terminal",
+        "org.wezfurlong.wezterm.desktop",
+    ])?;
+    fs::write(
+        sandbox.root.join(MIMEAPPS),
+        sandbox.read(MIMEAPPS)?
+            + "[Added Associations]\ntext/markdown=ghostwriter.desktop;\n",
+    )?;
+
+    assert_eq!(
+        sandbox.run(&["get", "--all", "text/markdown"])?,
+        "\
+mime         \tsource           \thandler            \tname \texists\tcommand                    
+text/markdown\tuser default     \tapostrophe.desktop \t     \tfalse \t                           
+text/*       \twildcard         \tHelix.desktop      \tHelix\ttrue  \twezterm start --cwd . -e hx
+text/markdown\tadded association\tghostwriter.desktop\t     \tfalse \t                           
+text/plain   \tsystem           \tHelix.desktop      \tHelix\ttrue  \twezterm start --cwd . -e hx
+"
+    );
+
+    // Regex and fallback handlers only apply to paths
+    let path = sandbox.path("rust.vim")?;
+    let candidates: serde_json::Value = serde_json::from_str(
+        &sandbox.run(&["get", "--all", "--path", &path, "--json"])?,
+    )?;
+    assert_eq!(
+        candidates,
+        serde_json::json!([
+            {
+                "mime": "text/*",
+                "source": "wildcard",
+                "handler": "Helix.desktop",
+                "name": "Helix",
+                "exists": true,
+                "command": "wezterm start --cwd . -e hx"
+            },
+            {
+                "mime": "text/plain",
+                "source": "system",
+                "handler": "Helix.desktop",
+                "name": "Helix",
+                "exists": true,
+                "command": "wezterm start --cwd . -e hx"
+            },
+            {
+                "mime": "",
+                "source": "fallback",
+                "handler": "hexyl.desktop",
+                "name": "",
+                "exists": false,
+                "command": ""
+            }
+        ])
+    );
+
+    Ok(())
+}